@@ -0,0 +1,568 @@
+//! Commands run from the command line without opening a window, so they work
+//! without a display server too:
+//!
+//! - `stox quote SYMBOL...` shows the latest quote of each symbol
+//! - `stox search QUERY` looks up symbols
+//! - `stox history SYMBOL` shows the bars of a range
+//! - `stox export SYMBOL` writes the bars of a range to CSV or JSON Lines
+//!
+//! Every command but `export` prints a table, or JSON with `--json`. `history`
+//! and `export` only cover regular trading hours unless `--sessions all` asks
+//! for pre and post-market bars too.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Write};
+
+use chrono::prelude::*;
+
+use rust_decimal::prelude::*;
+
+use serde::Serialize;
+
+use yahoo_finance_api::YQuoteItem;
+
+use crate::data_helper::{
+    stox_get_chart_interval, stox_get_quote_history, stox_get_quotes_batch, stox_search_symbol,
+};
+use crate::error::StoxError;
+use crate::format_helper::{fmt_change, fmt_change_percent, fmt_number, fmt_price, MISSING};
+use crate::history_export::{
+    history_rows, write_history, HistoryFormat, HistorySessions, HistoryTimeZone, HISTORY_COLUMNS,
+};
+
+/// Exit codes, so that scripts can tell what went wrong
+const EXIT_SUCCESS: i32 = 0;
+/// Anything not covered by the other codes, e.g. a file that could not be
+/// written or unexpected data from the server
+const EXIT_FAILURE: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_UNKNOWN_SYMBOL: i32 = 3;
+const EXIT_NETWORK: i32 = 4;
+const EXIT_RATE_LIMITED: i32 = 5;
+
+const QUOTE_USAGE: &str = "Usage: stox quote SYMBOL... [--json]";
+const SEARCH_USAGE: &str = "Usage: stox search QUERY [--json]";
+const HISTORY_USAGE: &str = "Usage: stox history SYMBOL [--range RANGE] [--interval INTERVAL] \
+    [--timezone utc|exchange] [--sessions regular|all] [--json]\n\
+    Pre and post-market bars are left out unless --sessions is all.";
+const EXPORT_USAGE: &str = "Usage: stox export SYMBOL [--range RANGE] [--interval INTERVAL] \
+    [--format csv|jsonl] [--timezone utc|exchange] [--sessions regular|all] [--output FILE]\n\
+    Pre and post-market bars are left out unless --sessions is all.";
+
+const DEFAULT_RANGE: &str = "1mo";
+
+/// Room between the columns of a table
+const COLUMN_SPACING: usize = 2;
+
+/// The arguments of a command, given by position or as `--name value`.
+struct Arguments {
+    positional: Vec<String>,
+    options: HashMap<String, String>,
+    json: bool,
+}
+
+impl Arguments {
+    /// Splits up `args`, as long as every option is one of `options` and
+    /// has a value. `--json` is the only option that takes none.
+    fn parse(args: &[String], options: &[&str]) -> Option<Self> {
+        let mut arguments = Self {
+            positional: vec![],
+            options: HashMap::new(),
+            json: false,
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let Some(name) = arg.strip_prefix("--") else {
+                arguments.positional.push(arg.clone());
+                continue;
+            };
+
+            if name == "json" {
+                arguments.json = true;
+            } else if options.contains(&name) {
+                arguments
+                    .options
+                    .insert(name.to_owned(), args.next()?.clone());
+            } else {
+                return None;
+            }
+        }
+
+        Some(arguments)
+    }
+
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(String::as_str)
+    }
+
+    /// The range and interval to fetch, the interval defaulting to the one
+    /// the charts use for the range.
+    fn range_and_interval(&self) -> (&str, &str) {
+        let range = self.option("range").unwrap_or(DEFAULT_RANGE);
+        let interval = self
+            .option("interval")
+            .unwrap_or_else(|| stox_get_chart_interval(range));
+
+        (range, interval)
+    }
+
+    fn time_zone(&self) -> Option<HistoryTimeZone> {
+        match self.option("timezone") {
+            Some(name) => HistoryTimeZone::from_name(name),
+            None => Some(HistoryTimeZone::Utc),
+        }
+    }
+
+    fn sessions(&self) -> Option<HistorySessions> {
+        match self.option("sessions") {
+            Some(name) => HistorySessions::from_name(name),
+            None => Some(HistorySessions::Regular),
+        }
+    }
+}
+
+/// The latest quote of a symbol, as printed with `--json`.
+#[derive(Serialize)]
+struct QuoteRow {
+    symbol: String,
+    name: String,
+    price: Option<f64>,
+    currency: String,
+    change: f64,
+    change_percent: f64,
+    exchange: String,
+    /// Set when the server could not be reached and the last known quote is
+    /// shown instead, in RFC 3339
+    as_of: Option<String>,
+}
+
+/// A search result, as printed with `--json`.
+#[derive(Serialize)]
+struct SearchRow {
+    symbol: String,
+    name: String,
+    #[serde(rename = "type")]
+    instrument_type: String,
+    exchange: String,
+}
+
+/// Runs the command in `args`, the arguments after the program name. Returns
+/// the exit code, or `None` to start the app when there is no command.
+pub fn run(args: &[String]) -> Option<i32> {
+    let (command, args) = args.split_first()?;
+
+    let code = match command.as_str() {
+        "quote" => quote(args),
+        "search" => search(args),
+        "history" => history(args),
+        "export" => export(args),
+        _ => return None,
+    };
+
+    Some(code)
+}
+
+fn quote(args: &[String]) -> i32 {
+    let Some(arguments) = Arguments::parse(args, &[]) else {
+        return usage_error(QUOTE_USAGE);
+    };
+    if arguments.positional.is_empty() {
+        return usage_error(QUOTE_USAGE);
+    }
+
+    let symbols: Vec<String> = arguments
+        .positional
+        .iter()
+        .map(|symbol| symbol.to_uppercase())
+        .collect();
+    let symbol_refs: Vec<&str> = symbols.iter().map(String::as_str).collect();
+
+    let mut quotes = match stox_get_quotes_batch(&symbol_refs) {
+        Ok(quotes) => quotes,
+        Err(err) => return fail(None, &err),
+    };
+
+    // The symbols found are still shown when some are not
+    let mut code = EXIT_SUCCESS;
+    let mut rows = vec![];
+    for symbol in symbols {
+        let Some((main_info, extended_info)) = quotes.remove(&symbol) else {
+            code = fail(Some(&symbol), &StoxError::UnknownSymbol);
+            continue;
+        };
+
+        let as_of = main_info
+            .as_of
+            .or(extended_info.as_of)
+            .map(|as_of| as_of.to_rfc3339_opts(SecondsFormat::Secs, true));
+        if let (Some(as_of), false) = (&as_of, arguments.json) {
+            eprintln!("stox: {}: last known quote as of {}", symbol, as_of);
+        }
+
+        rows.push((
+            QuoteRow {
+                symbol,
+                name: main_info.name,
+                price: main_info.last_quote.to_f64(),
+                currency: main_info.currency.clone(),
+                change: extended_info.market_change,
+                change_percent: extended_info.market_change_percent,
+                exchange: extended_info.exchange_name,
+                as_of,
+            },
+            fmt_price(main_info.last_quote, &main_info.currency),
+        ));
+    }
+
+    let printed = if arguments.json {
+        print_json(&rows.iter().map(|(row, _)| row).collect::<Vec<_>>())
+    } else {
+        print_table(
+            &["symbol", "name", "price", "change", "change %", "exchange"],
+            rows.into_iter()
+                .map(|(row, price)| {
+                    vec![
+                        row.symbol,
+                        row.name,
+                        price,
+                        fmt_change(row.change),
+                        fmt_change_percent(row.change_percent),
+                        row.exchange,
+                    ]
+                })
+                .collect(),
+        )
+    };
+
+    printed.map_or_else(write_failed, |_| code)
+}
+
+fn search(args: &[String]) -> i32 {
+    let Some(arguments) = Arguments::parse(args, &[]) else {
+        return usage_error(SEARCH_USAGE);
+    };
+    if arguments.positional.is_empty() {
+        return usage_error(SEARCH_USAGE);
+    }
+
+    let query = arguments.positional.join(" ");
+    let rows = match stox_search_symbol(&query).and_then(search_rows) {
+        Ok(rows) => rows,
+        Err(err) => return fail(Some(&query), &err),
+    };
+
+    let printed = if arguments.json {
+        print_json(&rows)
+    } else {
+        print_table(
+            &["symbol", "name", "type", "exchange"],
+            rows.into_iter()
+                .map(|row| vec![row.symbol, row.name, row.instrument_type, row.exchange])
+                .collect(),
+        )
+    };
+
+    printed.map_or_else(write_failed, |_| EXIT_SUCCESS)
+}
+
+/// A row per search result. Yahoo answers queries that match nothing with
+/// no results rather than an error, which is reported like an unknown symbol
+/// so that the exit code doesn't depend on where the results come from.
+fn search_rows(items: Vec<YQuoteItem>) -> Result<Vec<SearchRow>, StoxError> {
+    if items.is_empty() {
+        return Err(StoxError::UnknownSymbol);
+    }
+
+    Ok(items
+        .into_iter()
+        .map(|item| SearchRow {
+            symbol: item.symbol,
+            name: item.short_name,
+            instrument_type: item.type_display,
+            exchange: item.exchange,
+        })
+        .collect())
+}
+
+/// Prints the bars of a range, the same columns `export` writes.
+fn history(args: &[String]) -> i32 {
+    let Some(arguments) = Arguments::parse(args, &["range", "interval", "timezone", "sessions"])
+    else {
+        return usage_error(HISTORY_USAGE);
+    };
+    let ([symbol], Some(time_zone), Some(sessions)) = (
+        &arguments.positional[..],
+        arguments.time_zone(),
+        arguments.sessions(),
+    ) else {
+        return usage_error(HISTORY_USAGE);
+    };
+
+    let symbol = symbol.to_uppercase();
+    let (range, interval) = arguments.range_and_interval();
+
+    let info = match stox_get_quote_history(&symbol, range, interval) {
+        Ok(info) => info,
+        Err(err) => return fail(Some(&symbol), &err),
+    };
+
+    let printed = history_rows(&info, time_zone, sessions).and_then(|rows| {
+        if arguments.json {
+            print_json(&rows)
+        } else {
+            print_table(
+                &HISTORY_COLUMNS,
+                rows.into_iter()
+                    .map(|row| {
+                        vec![
+                            row.timestamp,
+                            fmt_number(row.open),
+                            fmt_number(row.high),
+                            fmt_number(row.low),
+                            fmt_number(Some(row.close)),
+                            fmt_number(row.adjclose),
+                            row.volume
+                                .map_or_else(|| MISSING.to_owned(), |volume| volume.to_string()),
+                        ]
+                    })
+                    .collect(),
+            )
+        }
+    });
+
+    printed.map_or_else(write_failed, |_| EXIT_SUCCESS)
+}
+
+/// Writes the bars of a symbol to a file or stdout, e.g.
+/// `stox export AAPL --range 1y --interval 1wk --format jsonl`.
+fn export(args: &[String]) -> i32 {
+    let Some(arguments) = Arguments::parse(
+        args,
+        &[
+            "range", "interval", "format", "timezone", "sessions", "output",
+        ],
+    ) else {
+        return usage_error(EXPORT_USAGE);
+    };
+    let format = match arguments.option("format") {
+        Some(name) => HistoryFormat::from_name(name),
+        None => Some(HistoryFormat::Csv),
+    };
+    let ([symbol], Some(format), Some(time_zone), Some(sessions), false) = (
+        &arguments.positional[..],
+        format,
+        arguments.time_zone(),
+        arguments.sessions(),
+        arguments.json,
+    ) else {
+        return usage_error(EXPORT_USAGE);
+    };
+
+    let symbol = symbol.to_uppercase();
+    let (range, interval) = arguments.range_and_interval();
+
+    let info = match stox_get_quote_history(&symbol, range, interval) {
+        Ok(info) => info,
+        Err(err) => return fail(Some(&symbol), &err),
+    };
+
+    let written = match arguments.option("output") {
+        Some(path) => File::create(path)
+            .and_then(|mut file| write_history(&mut file, &info, format, time_zone, sessions)),
+        None => {
+            let mut stdout = io::stdout().lock();
+            write_history(&mut stdout, &info, format, time_zone, sessions)
+                .and_then(|_| stdout.flush())
+        }
+    };
+
+    written.map_or_else(write_failed, |_| EXIT_SUCCESS)
+}
+
+/// Reports `err` for `symbol`, if it was about one, and returns its exit
+/// code.
+fn fail(symbol: Option<&str>, err: &StoxError) -> i32 {
+    match symbol {
+        Some(symbol) => eprintln!("stox: {}: {}", symbol, err.message()),
+        None => eprintln!("stox: {}", err.message()),
+    }
+
+    exit_code(err)
+}
+
+fn exit_code(err: &StoxError) -> i32 {
+    match err {
+        StoxError::UnknownSymbol => EXIT_UNKNOWN_SYMBOL,
+        StoxError::Network => EXIT_NETWORK,
+        StoxError::RateLimited => EXIT_RATE_LIMITED,
+        StoxError::ParseError { .. } | StoxError::NoData => EXIT_FAILURE,
+    }
+}
+
+/// Reports that the output could not be written. Output piped into e.g.
+/// `head` stops being read halfway, which is not worth reporting.
+fn write_failed(err: io::Error) -> i32 {
+    if err.kind() == io::ErrorKind::BrokenPipe {
+        return EXIT_SUCCESS;
+    }
+
+    eprintln!("stox: {}", err);
+    EXIT_FAILURE
+}
+
+fn usage_error(usage: &str) -> i32 {
+    eprintln!("{}", usage);
+    EXIT_USAGE
+}
+
+fn print_json(value: &impl Serialize) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+
+    serde_json::to_writer_pretty(&mut stdout, value)?;
+    writeln!(stdout)
+}
+
+fn print_table(header: &[&str], rows: Vec<Vec<String>>) -> io::Result<()> {
+    write_table(&mut io::stdout().lock(), header, rows)
+}
+
+/// Writes `rows` under `header`, in capitals, in columns as wide as their
+/// widest cell.
+fn write_table(writer: &mut impl Write, header: &[&str], rows: Vec<Vec<String>>) -> io::Result<()> {
+    let mut widths: Vec<usize> = header.iter().map(|title| title.chars().count()).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let header = header.iter().map(|title| title.to_uppercase()).collect();
+
+    for row in std::iter::once(header).chain(rows) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width + COLUMN_SPACING))
+            .collect();
+
+        writeln!(writer, "{}", line.concat().trim_end())?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn arguments_are_split_up() {
+        let arguments = Arguments::parse(
+            &args(&["AAPL", "--range", "1y", "--json", "MSFT"]),
+            &["range", "interval"],
+        )
+        .unwrap();
+
+        assert_eq!(arguments.positional, ["AAPL", "MSFT"]);
+        assert_eq!(arguments.option("range"), Some("1y"));
+        assert_eq!(arguments.option("interval"), None);
+        assert!(arguments.json);
+    }
+
+    #[test]
+    fn unknown_options_and_missing_values_are_rejected() {
+        assert!(Arguments::parse(&args(&["AAPL", "--output", "x"]), &["range"]).is_none());
+        assert!(Arguments::parse(&args(&["AAPL", "--range"]), &["range"]).is_none());
+    }
+
+    #[test]
+    fn interval_defaults_to_the_charts() {
+        let options = ["range", "interval"];
+        let range_and_interval = |arguments: &[&str]| {
+            let arguments = Arguments::parse(&args(arguments), &options).unwrap();
+            let (range, interval) = arguments.range_and_interval();
+
+            (range.to_owned(), interval.to_owned())
+        };
+
+        assert_eq!(range_and_interval(&[]), ("1mo".into(), "60m".into()));
+        assert_eq!(
+            range_and_interval(&["--range", "1y"]),
+            ("1y".into(), "1d".into())
+        );
+        assert_eq!(
+            range_and_interval(&["--range", "1y", "--interval", "1wk"]),
+            ("1y".into(), "1wk".into())
+        );
+    }
+
+    #[test]
+    fn time_zone_defaults_to_utc() {
+        let time_zone = |arguments: &[&str]| {
+            Arguments::parse(&args(arguments), &["timezone"])
+                .unwrap()
+                .time_zone()
+        };
+
+        assert!(time_zone(&[]) == Some(HistoryTimeZone::Utc));
+        assert!(time_zone(&["--timezone", "exchange"]) == Some(HistoryTimeZone::Exchange));
+        assert!(time_zone(&["--timezone", "local"]).is_none());
+    }
+
+    #[test]
+    fn sessions_default_to_regular_hours() {
+        let sessions = |arguments: &[&str]| {
+            Arguments::parse(&args(arguments), &["sessions"])
+                .unwrap()
+                .sessions()
+        };
+
+        assert!(sessions(&[]) == Some(HistorySessions::Regular));
+        assert!(sessions(&["--sessions", "all"]) == Some(HistorySessions::All));
+        assert!(sessions(&["--sessions", "extended"]).is_none());
+    }
+
+    #[test]
+    fn errors_have_their_own_exit_codes() {
+        assert_eq!(exit_code(&StoxError::UnknownSymbol), EXIT_UNKNOWN_SYMBOL);
+        assert_eq!(exit_code(&StoxError::Network), EXIT_NETWORK);
+        assert_eq!(exit_code(&StoxError::RateLimited), EXIT_RATE_LIMITED);
+        assert_eq!(exit_code(&StoxError::parse("chart")), EXIT_FAILURE);
+        assert_eq!(exit_code(&StoxError::NoData), EXIT_FAILURE);
+    }
+
+    #[test]
+    fn searches_without_results_are_unknown_symbols() {
+        let err = search_rows(vec![]).err().unwrap();
+
+        assert_eq!(err, StoxError::UnknownSymbol);
+        assert_eq!(exit_code(&err), EXIT_UNKNOWN_SYMBOL);
+    }
+
+    #[test]
+    fn table_columns_are_aligned() {
+        let mut buffer = vec![];
+        write_table(
+            &mut buffer,
+            &["symbol", "name"],
+            vec![
+                vec!["AAPL".to_owned(), "Apple Inc.".to_owned()],
+                vec!["BRK-B".to_owned(), "Berkshire Hathaway".to_owned()],
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "SYMBOL  NAME\n\
+             AAPL    Apple Inc.\n\
+             BRK-B   Berkshire Hathaway\n"
+        );
+    }
+}
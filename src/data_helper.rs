@@ -1,324 +1,647 @@
-use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::ops::Range;
 
 use chrono::prelude::*;
 
+use gtk4::glib;
+
 use rust_decimal::Decimal;
-use rusty_money::{iso, Money};
 
-use yahoo_finance_api::YahooConnector;
 use yahoo_finance_api::*;
 
-static PROVIDER: once_cell::sync::Lazy<YahooConnector> =
-    once_cell::sync::Lazy::new(|| YahooConnector::new());
+use crate::error::Result;
+use crate::providers::{self, QuoteProvider};
 
-macro_rules! stat_fmt {
-    ($stat:expr) => {
-        $stat["fmt"]
-            .as_str()
-            .unwrap_or("N/A")
-            .to_string()
-            .to_uppercase()
-    };
-}
+static PROVIDER: once_cell::sync::Lazy<Box<dyn QuoteProvider>> =
+    once_cell::sync::Lazy::new(providers::from_env);
 
 pub struct MainInfo {
-    pub last_quote: String,
+    pub last_quote: Decimal,
     pub name: String,
     pub instrument_type: String,
     pub currency: String,
-    pub chart: Vec<YQuoteBlock>,
     pub bankruptcy: bool,
+    /// Set when this is the last known value, shown while offline
+    pub as_of: Option<DateTime<Utc>>,
 }
 
 pub struct ExtendedInfo {
     pub exchange_name: String,
-    pub day_range: String,
-    pub market_change: String,
-    pub market_change_percent: String,
+    pub market_change: f64,
+    pub market_change_percent: f64,
+    /// Set when this is the last known value, shown while offline
+    pub as_of: Option<DateTime<Utc>>,
 }
 
-pub struct StatsInfo {
-    pub open: String,
-    pub high: String,
-    pub low: String,
-    pub volume: String,
-    pub pe_ratio: String,
-    pub market_cap: String,
-    pub dividend_yield: String,
-    pub beta: String,
-    pub eps: String,
+/// The OHLCV bars of one chart range, oldest first.
+pub struct ChartInfo {
+    pub quotes: Vec<Quote>,
+    pub currency: String,
+    /// The IANA name of the exchange's time zone, e.g. "America/New_York"
+    pub timezone: String,
+    /// The exchange's current offset from UTC, in seconds
+    pub utc_offset: i32,
+    /// The last close of the day before, only known for the one day range
+    pub previous_close: Option<f64>,
+    /// The sessions of every day of an intraday range, empty for longer ones
+    pub trading_periods: Vec<TradingPeriod>,
+    /// Which values of each bar Yahoo left out, as yahoo_finance_api reads
+    /// them as 0. Bars past the end of it have every value.
+    pub gaps: Vec<QuoteGaps>,
+    /// Set when these are the last known bars, shown while offline
+    pub as_of: Option<DateTime<Utc>>,
 }
 
-impl ExtendedInfo {
-    pub fn market_change_neg(&self) -> bool {
-        self.market_change.starts_with('-')
-    }
+/// The values Yahoo left out of a bar. A bar always has a close.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QuoteGaps {
+    pub open: bool,
+    pub high: bool,
+    pub low: bool,
+    pub volume: bool,
+    pub adjclose: bool,
 }
 
-pub fn stox_search_symbol(symbol: &str) -> Result<Vec<YQuoteItem>, anyhow::Error> {
-    Ok(PROVIDER.search_ticker(&urlencoding::encode(symbol))?.quotes)
+/// The part of the trading day a bar falls in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MarketSession {
+    Pre,
+    Regular,
+    Post,
 }
 
-pub fn stox_get_main_info(symbol: &str) -> Result<MainInfo> {
-    let latest_quotes = PROVIDER.get_latest_quotes(&urlencoding::encode(symbol), "1h")?;
+/// One session of one trading day, from `start` up to but not including
+/// `end`.
+pub struct TradingPeriod {
+    pub session: MarketSession,
+    pub start: u64,
+    pub end: u64,
+}
 
-    let last_quote = latest_quotes.last_quote()?.close;
-    let last_quote = (last_quote * 100.0).round() as i64;
-    let last_quote = Decimal::new(last_quote, 2); // limit to two decimal places
+/// Any of the stats may be missing, e.g. ETFs have no P/E ratio.
+#[derive(Default)]
+pub struct StatsInfo {
+    pub open: Option<f64>,
+    pub high: Option<f64>,
+    pub low: Option<f64>,
+    pub volume: Option<u64>,
+    pub pe_ratio: Option<f64>,
+    pub market_cap: Option<u64>,
+    /// A fraction, not a percentage
+    pub dividend_yield: Option<f64>,
+    pub beta: Option<f64>,
+    pub eps: Option<f64>,
+    /// Set when these are the last known values, shown while offline
+    pub as_of: Option<DateTime<Utc>>,
+}
 
-    let quote_item = &PROVIDER.search_ticker(symbol)?.quotes[0];
-    let mut name = &quote_item.long_name;
-    if name.is_empty() {
-        name = &quote_item.short_name;
+impl ChartInfo {
+    /// Whether the range closed lower than the day before closed, or than it
+    /// opened when that is not known.
+    pub fn change_neg(&self) -> bool {
+        let first = self
+            .previous_close
+            .or_else(|| self.quotes.first().map(|quote| quote.close));
+        let last = self.quotes.last().map(|quote| quote.close);
+
+        last < first
     }
 
-    let meta = &latest_quotes.chart.result[0].meta;
-    let currency = meta.currency.to_uppercase();
-    let instrument_type = meta.instrument_type.to_string();
+    /// The session a bar starting at `timestamp` falls in, if it is known.
+    pub fn session_at(&self, timestamp: u64) -> Option<MarketSession> {
+        self.trading_periods
+            .iter()
+            .find(|period| (period.start..period.end).contains(&timestamp))
+            .map(|period| period.session)
+    }
 
-    let mut main_info = MainInfo {
-        last_quote: last_quote.to_string(),
-        name: name.to_string(),
-        instrument_type,
-        currency: currency.clone(),
-        chart: latest_quotes.chart.result,
+    /// The exchange's time zone by name, which knows about daylight saving
+    /// time. GLib falls back to UTC for names the system does not know, in
+    /// which case the current offset is used instead.
+    pub fn exchange_time_zone(&self) -> glib::TimeZone {
+        let zone = glib::TimeZone::new(Some(&self.timezone));
+        let offset = glib::DateTime::now(&zone)
+            .map(|now| now.utc_offset().as_seconds())
+            .ok();
+
+        if offset == Some(self.utc_offset as i64) {
+            return zone;
+        }
 
-        // Typically, if a company is undergoing bankruptcy they will
-        // add "Q" to the end of their stock symbol in 5-chars length
-        bankruptcy: symbol.ends_with('Q') && symbol.len() == 5,
-    };
+        let sign = if self.utc_offset < 0 { '-' } else { '+' };
+        let offset = self.utc_offset.unsigned_abs();
+        glib::TimeZone::new(Some(&format!(
+            "{}{:02}:{:02}",
+            sign,
+            offset / 3600,
+            offset % 3600 / 60
+        )))
+    }
+}
 
-    if let Some(currency) = iso::find(&currency) {
-        let last_quote = Money::from_decimal(last_quote, currency).to_string();
-        main_info.last_quote = last_quote;
+impl ExtendedInfo {
+    pub fn market_change_neg(&self) -> bool {
+        self.market_change < 0.0
     }
+}
 
-    Ok(main_info)
+pub fn stox_is_offline() -> bool {
+    providers::is_offline()
 }
 
-pub fn stox_get_extended_info(symbol: &str) -> Result<ExtendedInfo> {
-    let url = format!(
-        "https://query1.finance.yahoo.com/v7/finance/options/{}",
-        urlencoding::encode(symbol)
-    );
-
-    let data = reqwest::blocking::get(url)?.text()?;
-    let data: serde_json::Value = serde_json::from_str(&data)?;
-
-    let quote = &data["optionChain"]["result"][0]["quote"];
-
-    let exchange_name = quote["fullExchangeName"]
-        .as_str()
-        .context("expected exchange name")?
-        .to_owned();
-    let day_range = quote["regularMarketDayRange"]
-        .as_str()
-        .context("expected day range")?
-        .to_owned();
-
-    let mut market_change = format!(
-        "{:.2}",
-        quote["regularMarketChange"]
-            .as_f64()
-            .context("expected market change")?
-    );
-    if !market_change.starts_with('-') {
-        market_change.insert(0, '+');
-    }
+/// While offline, last known values are served without trying the network.
+/// Requests still go out for data that was never cached.
+pub fn stox_set_offline(offline: bool) {
+    providers::set_offline(offline)
+}
 
-    let mut market_change_percent = format!(
-        "{:.2}",
-        quote["regularMarketChangePercent"]
-            .as_f64()
-            .context("expected market change percent")?
-    ) + "%";
-    if !market_change_percent.starts_with('-') {
-        market_change_percent.insert(0, '+');
-    }
+pub fn stox_search_symbol(symbol: &str) -> Result<Vec<YQuoteItem>> {
+    PROVIDER.search(symbol)
+}
 
-    Ok(ExtendedInfo {
-        exchange_name,
-        day_range,
-        market_change,
-        market_change_percent,
-    })
+pub fn stox_get_main_info(symbol: &str) -> Result<MainInfo> {
+    PROVIDER.main_info(symbol)
+}
+
+pub fn stox_get_extended_info(symbol: &str) -> Result<ExtendedInfo> {
+    PROVIDER.extended_info(symbol)
 }
 
 pub fn stox_get_stats_info(symbol: &str) -> Result<StatsInfo> {
-    let url = format!(
-        "{}{}{}",
-        "https://query1.finance.yahoo.com/v11/finance/quoteSummary/",
-        urlencoding::encode(symbol),
-        "?modules=defaultKeyStatistics,summaryDetail"
-    );
-
-    let data = reqwest::blocking::get(url)?.text()?;
-    let data: serde_json::Value = serde_json::from_str(&data)?;
-
-    let result = &data["quoteSummary"]["result"][0];
-    let summary_detail = &result["summaryDetail"];
-
-    let open = stat_fmt!(summary_detail["open"]);
-    let high = stat_fmt!(summary_detail["dayHigh"]);
-    let low = stat_fmt!(summary_detail["dayLow"]);
-    let volume = stat_fmt!(summary_detail["volume"]);
-    let pe_ratio = stat_fmt!(summary_detail["trailingPE"]);
-    let market_cap = stat_fmt!(summary_detail["marketCap"]);
-    let dividend_yield = stat_fmt!(summary_detail["dividendYield"]);
-    let beta = stat_fmt!(summary_detail["beta"]);
-    let eps = stat_fmt!(result["defaultKeyStatistics"]["trailingEps"]);
-
-    Ok(StatsInfo {
-        open,
-        high,
-        low,
-        volume,
-        pe_ratio,
-        market_cap,
-        dividend_yield,
-        beta,
-        eps,
-    })
+    PROVIDER.stats_info(symbol)
 }
 
-pub fn stox_get_sidebar_info(symbol: &str) -> Result<(MainInfo, ExtendedInfo)> {
-    Ok((stox_get_main_info(symbol)?, stox_get_extended_info(symbol)?))
+/// Fetches the sidebar info for many symbols in as few requests as the
+/// provider allows. Unknown symbols are missing from the result.
+pub fn stox_get_quotes_batch(
+    symbols: &[&str],
+) -> Result<HashMap<String, (MainInfo, ExtendedInfo)>> {
+    PROVIDER.quotes_batch(symbols)
 }
 
-pub fn stox_get_datagrid_info(symbol: &str) -> Result<(MainInfo, ExtendedInfo, StatsInfo)> {
+pub fn stox_get_datagrid_info(symbol: &str) -> Result<(MainInfo, ExtendedInfo, Option<StatsInfo>)> {
     Ok((
         stox_get_main_info(symbol)?,
         stox_get_extended_info(symbol)?,
         // The stats are non-critical so if for some reason they fail to load
         // we can do without them
-        stox_get_stats_info(symbol).unwrap_or(StatsInfo {
-            open: "???".to_owned(),
-            high: "???".to_owned(),
-            low: "???".to_owned(),
-            volume: "???".to_owned(),
-            pe_ratio: "???".to_owned(),
-            market_cap: "???".to_owned(),
-            dividend_yield: "???".to_owned(),
-            beta: "???".to_owned(),
-            eps: "???".to_owned(),
-        }),
+        stox_get_stats_info(symbol).ok(),
     ))
 }
 
-pub fn stox_get_chart_x_axis(
-    main_info: &MainInfo,
-    range: &str,
-) -> Result<Vec<String>, anyhow::Error> {
-    let mut axis: Vec<String> = vec![];
-
-    for index in &main_info.chart {
-        for timestamp in &index.timestamp {
-            // The x-axis should show different things depending on the range.
-            // For example, in the span of one day, we should show the time
-            // instead of the day.
-            match range {
-                "1d" => {
-                    let mut hour = Utc
-                        .timestamp_opt(*timestamp as i64, 0)
-                        .single()
-                        .context("expected timestamp")?
-                        .hour()
-                        .to_string();
-                    hour.push_str(&(":".to_string() + "00")); // the hour is now our total time
-                    axis.push(hour.to_string());
-                }
-                "5d" | "1wk" | "1mo" => {
-                    let mut day = Utc
-                        .timestamp_opt(*timestamp as i64, 0)
-                        .single()
-                        .context("expected timestamp")?
-                        .day()
-                        .to_string();
-                    let month = Utc
-                        .timestamp_opt(*timestamp as i64, 0)
-                        .single()
-                        .context("expected timestamp")?
-                        .month()
-                        .to_string();
-                    day.push_str(&("/".to_string() + &month.to_string()));
-                    axis.push(day.to_string());
-                }
-                "3mo" | "6mo" | "1y" | "2y" => {
-                    let month = Utc
-                        .timestamp_opt(*timestamp as i64, 0)
-                        .single()
-                        .context("expected timestamp")?
-                        .month()
-                        .to_string();
-                    axis.push(month.to_string());
-                }
-                "5y" | "10y" | "ytd" | "max" => {
-                    let year = Utc
-                        .timestamp_opt(*timestamp as i64, 0)
-                        .single()
-                        .context("expected timestamp")?
-                        .year()
-                        .to_string();
-                    axis.push(year.to_string());
-                }
-                &_ => {
-                    // default, something wildly ridiculous
-                    unimplemented!()
-                }
+/// What an x-axis label shows, from the finest to the coarsest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChartTimeLabel {
+    Time,
+    Day,
+    Month,
+    Year,
+}
+
+/// A spacing between x-axis ticks, in wall-clock time.
+#[derive(Clone, Copy)]
+enum ChartTimeStep {
+    Minutes(i64),
+    Days(i64),
+    Weeks,
+    Months(i32),
+    Years(i32),
+}
+
+/// The x-axis tick spacings to pick from, finest first.
+const CHART_TIME_STEPS: [ChartTimeStep; 16] = [
+    ChartTimeStep::Minutes(1),
+    ChartTimeStep::Minutes(5),
+    ChartTimeStep::Minutes(15),
+    ChartTimeStep::Minutes(30),
+    ChartTimeStep::Minutes(60),
+    ChartTimeStep::Minutes(120),
+    ChartTimeStep::Minutes(240),
+    ChartTimeStep::Days(1),
+    ChartTimeStep::Weeks,
+    ChartTimeStep::Months(1),
+    ChartTimeStep::Months(3),
+    ChartTimeStep::Months(6),
+    ChartTimeStep::Years(1),
+    ChartTimeStep::Years(2),
+    ChartTimeStep::Years(5),
+    ChartTimeStep::Years(10),
+];
+
+impl ChartTimeStep {
+    /// Which step `time` falls in, counting from the start of the calendar.
+    fn of(self, time: &NaiveDateTime) -> i64 {
+        match self {
+            Self::Minutes(minutes) => {
+                let minute = time.hour() * 60 + time.minute();
+                (time.num_days_from_ce() as i64 * 24 * 60 + minute as i64) / minutes
+            }
+            Self::Days(days) => time.num_days_from_ce() as i64 / days,
+            Self::Weeks => {
+                let week = time.iso_week();
+                week.year() as i64 * 53 + week.week() as i64
             }
+            Self::Months(months) => ((time.year() * 12 + time.month0() as i32) / months) as i64,
+            Self::Years(years) => (time.year() / years) as i64,
+        }
+    }
+
+    /// The label for a bar at `time` that starts a new step after `previous`.
+    /// Intraday ticks show the day when it changes, longer ones the year.
+    fn label(self, time: &NaiveDateTime, previous: &NaiveDateTime) -> ChartTimeLabel {
+        match self {
+            Self::Minutes(_) if time.date() == previous.date() => ChartTimeLabel::Time,
+            Self::Minutes(_) => ChartTimeLabel::Day,
+            _ if time.year() != previous.year() => ChartTimeLabel::Year,
+            Self::Days(_) | Self::Weeks => ChartTimeLabel::Day,
+            Self::Months(_) => ChartTimeLabel::Month,
+            Self::Years(_) => ChartTimeLabel::Year,
         }
     }
+}
 
-    axis.dedup(); // remove duplicates
+/// The bars of `visible` that start a new step of wall-clock time, with what
+/// their label should show. The step is the finest that keeps to `max_ticks`,
+/// so zooming in brings in more detailed labels.
+///
+/// `times` holds the wall-clock time of every bar, so that the first bar in
+/// view is compared against the one before it.
+pub fn stox_get_chart_x_ticks(
+    times: &[NaiveDateTime],
+    visible: Range<usize>,
+    max_ticks: usize,
+) -> Vec<(usize, ChartTimeLabel)> {
+    let starts = |step: ChartTimeStep| -> Vec<(usize, ChartTimeLabel)> {
+        visible
+            .clone()
+            .filter(|&index| index > 0 && index < times.len())
+            .filter(|&index| step.of(&times[index]) != step.of(&times[index - 1]))
+            .map(|index| (index, step.label(&times[index], &times[index - 1])))
+            .collect()
+    };
+
+    let mut ticks = vec![];
+    for step in CHART_TIME_STEPS {
+        ticks = starts(step);
+        if ticks.len() <= max_ticks {
+            return ticks;
+        }
+    }
 
-    Ok(axis)
+    // Even the coarsest step is too dense, so only every so many of its
+    // ticks are kept
+    if max_ticks == 0 {
+        return vec![];
+    }
+    let every = ticks.len().div_ceil(max_ticks);
+    ticks.into_iter().step_by(every).collect()
 }
 
-pub fn stox_get_chart_y_axis(extended_info: &ExtendedInfo) -> Result<Vec<f64>, anyhow::Error> {
-    // We have our range, but we need to make it a vec of points.
-    let i = extended_info.day_range.trim();
-    let i2: Vec<&str> = i.split(" - ").collect();
-    let start: f64 = i2[0].parse()?;
-    let end: f64 = i2[1].parse()?;
-    let step_part1 = (start + end) / 2.0;
-    let step = (step_part1 - start) / 2.0;
-
-    Ok(vec![
-        start,
-        start + step,
-        step_part1,
-        step_part1 + step,
-        end,
-    ])
+/// At most `max_ticks` round prices between `min` and `max`, spaced 1, 2 or 5
+/// times a power of ten apart.
+pub fn stox_get_chart_y_ticks(min: f64, max: f64, max_ticks: usize) -> Vec<f64> {
+    if !min.is_finite() || !max.is_finite() || max_ticks < 2 {
+        return vec![];
+    }
+    if max <= min {
+        return vec![min];
+    }
+
+    // Ticks only go where their multiple of the step falls in range, so the
+    // step is the smallest round one that keeps to `max_ticks`. Any step
+    // below this one would fit more than that many
+    let mut step = nice_number((max - min) / (max_ticks + 1) as f64);
+    while (max / step).floor() - (min / step).ceil() + 1.0 > max_ticks as f64 {
+        step = nice_number(step * 1.5);
+    }
+
+    let first = (min / step).ceil();
+    let last = (max / step).floor();
+
+    // Multiplying instead of adding up the steps keeps rounding errors from
+    // piling up
+    (first as i64..=last as i64)
+        .map(|tick| tick as f64 * step)
+        .collect()
 }
 
-pub fn stox_get_quotes(symbol: String, range: &str) -> Result<Vec<f64>, anyhow::Error> {
-    let response = PROVIDER.get_quote_range(&symbol, "1m", range)?;
+/// The multiples of each power of ten to put log scale ticks at, most first.
+const LOG_TICK_MULTIPLES: [&[f64]; 4] =
+    [&[1.0, 2.0, 3.0, 5.0], &[1.0, 2.0, 5.0], &[1.0, 3.0], &[1.0]];
 
-    let mut axis: Vec<f64> = vec![];
+/// At most `max_ticks` round prices between `min` and `max` for a log scale,
+/// at powers of ten and as many of their round multiples in between as fit.
+/// Ranges that do not at least double look linear enough for the usual ticks.
+pub fn stox_get_chart_log_y_ticks(min: f64, max: f64, max_ticks: usize) -> Vec<f64> {
+    if min <= 0.0 || max / min < 2.0 || max_ticks < 2 {
+        return stox_get_chart_y_ticks(min, max, max_ticks);
+    }
 
-    for index_first in response.quotes().into_iter() {
-        for index in index_first.iter() {
-            let quote = index.close;
+    let (low, high) = (min.log10(), max.log10());
+    // Ticks are spread unevenly, so the closest two need to keep to the
+    // spacing of `max_ticks` evenly spread ones
+    let min_gap = (high - low) / (max_ticks - 1) as f64;
+
+    let multiples = LOG_TICK_MULTIPLES.iter().find(|multiples| {
+        multiples
+            .iter()
+            .zip(multiples.iter().skip(1).chain(&[10.0]))
+            .all(|(multiple, next)| (next / multiple).log10() >= min_gap)
+    });
+    let (multiples, decade_step) = match multiples {
+        Some(multiples) => (*multiples, 1),
+        None => (&[1.0][..], min_gap.ceil() as i32),
+    };
 
-            axis.push(quote);
-        }
+    let first_decade = low.floor() as i32;
+    let last_decade = high.floor() as i32;
+    let ticks: Vec<f64> = (first_decade..=last_decade)
+        .filter(|decade| decade.rem_euclid(decade_step) == 0)
+        .flat_map(|decade| {
+            multiples
+                .iter()
+                .map(move |multiple| multiple * 10f64.powi(decade))
+        })
+        .filter(|tick| (min..=max).contains(tick))
+        .collect();
+
+    // Short ranges can fall between the round multiples
+    if ticks.len() < 3 {
+        return stox_get_chart_y_ticks(min, max, max_ticks);
+    }
+
+    ticks
+}
+
+/// The smallest of 1, 2, 5 or 10 times a power of ten that is at least
+/// `value`.
+fn nice_number(value: f64) -> f64 {
+    let magnitude = 10f64.powf(value.log10().floor());
+
+    let nice = match value / magnitude {
+        fraction if fraction <= 1.0 => 1.0,
+        fraction if fraction <= 2.0 => 2.0,
+        fraction if fraction <= 5.0 => 5.0,
+        _ => 10.0,
+    };
+
+    nice * magnitude
+}
+
+/// The bar size for each chart range, coarse enough to keep long ranges to a
+/// few hundred bars and within what Yahoo serves for that range.
+pub fn stox_get_chart_interval(range: &str) -> &'static str {
+    match range {
+        "1d" => "2m",
+        "5d" => "15m",
+        "1mo" => "60m",
+        "3mo" | "6mo" | "1y" | "ytd" => "1d",
+        "2y" | "5y" => "1wk",
+        _ => "1mo",
     }
+}
 
-    Ok(axis)
+/// The intervals Yahoo serves bars of `range` at, shortest first. The
+/// shorter an interval, the less far back it goes.
+pub fn stox_get_chart_intervals(range: &str) -> &'static [&'static str] {
+    match range {
+        "1d" | "5d" => &["1m", "2m", "5m", "15m", "30m", "60m", "1d"],
+        "1mo" => &["2m", "5m", "15m", "30m", "60m", "1d", "1wk"],
+        "3mo" | "6mo" | "1y" | "ytd" => &["60m", "1d", "1wk", "1mo"],
+        _ => &["1d", "1wk", "1mo"],
+    }
 }
 
-pub fn stox_scale_quotes(quotes: &mut [f64], height: i32) -> Vec<f64> {
-    let max = quotes.iter().max_by(|a, b| a.total_cmp(b)).unwrap();
-    let min = quotes.iter().min_by(|a, b| a.total_cmp(b)).unwrap();
-    let mut ret: Vec<f64> = vec![];
-    for i in quotes.iter() {
-        let mut x = (i - min) / (max - min) * ((height as f64) - 5.0);
-        if x < 0.0 {
-            // Sanity check: Don't push negative numbers under the graph
-            x *= -1.0;
+pub fn stox_get_quotes(symbol: String, range: &str) -> Result<ChartInfo> {
+    stox_get_quote_history(&symbol, range, stox_get_chart_interval(range))
+}
+
+/// The bars of `range` at any `interval` Yahoo serves for it, e.g. "1d" or
+/// "1wk".
+pub fn stox_get_quote_history(symbol: &str, range: &str, interval: &str) -> Result<ChartInfo> {
+    PROVIDER.quote_history(symbol, interval, range)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Ranges as tall and as short as the charts get, at every magnitude
+    const RANGES: [(f64, f64); 8] = [
+        (0.0012, 0.0019),
+        (0.1234, 0.1567),
+        (0.87, 1.12),
+        (9.5, 31.2),
+        (148.73, 152.91),
+        (2987.3, 3214.9),
+        (31200.0, 34800.0),
+        (12.0, 4800.0),
+    ];
+
+    fn assert_ticks(actual: &[f64], expected: &[f64]) {
+        assert_eq!(actual.len(), expected.len(), "{:?}", actual);
+        for (actual_tick, expected_tick) in actual.iter().zip(expected) {
+            assert!(
+                (actual_tick - expected_tick).abs() < 1e-9,
+                "{:?} is not {:?}",
+                actual,
+                expected
+            );
         }
-        ret.push(x);
     }
-    ret
+
+    #[test]
+    fn y_ticks_of_a_flat_range() {
+        assert_ticks(&stox_get_chart_y_ticks(152.59, 152.59, 6), &[152.59]);
+    }
+
+    #[test]
+    fn y_ticks_of_prices_in_the_thousands() {
+        assert_ticks(
+            &stox_get_chart_y_ticks(2987.3, 3214.9, 6),
+            &[3000.0, 3050.0, 3100.0, 3150.0, 3200.0],
+        );
+    }
+
+    #[test]
+    fn y_ticks_of_sub_dollar_prices() {
+        assert_ticks(
+            &stox_get_chart_y_ticks(0.1234, 0.1567, 5),
+            &[0.13, 0.14, 0.15],
+        );
+    }
+
+    #[test]
+    fn y_ticks_keep_to_max_ticks() {
+        for (min, max) in RANGES {
+            for max_ticks in 2..12 {
+                let ticks = stox_get_chart_y_ticks(min, max, max_ticks);
+
+                assert!(!ticks.is_empty(), "{} to {}", min, max);
+                assert!(ticks.len() <= max_ticks, "{} to {}: {:?}", min, max, ticks);
+            }
+        }
+    }
+
+    #[test]
+    fn y_ticks_are_round_and_in_range() {
+        for (min, max) in RANGES {
+            let ticks = stox_get_chart_y_ticks(min, max, 6);
+            let step = ticks[1] - ticks[0];
+
+            let fraction = step / 10f64.powf(step.log10().floor());
+            assert!(
+                [1.0, 2.0, 5.0, 10.0]
+                    .iter()
+                    .any(|nice| (fraction - nice).abs() < 1e-6),
+                "{} to {}: {:?}",
+                min,
+                max,
+                ticks
+            );
+
+            for tick in ticks.iter() {
+                assert!(
+                    (min..=max).contains(tick),
+                    "{} to {}: {:?}",
+                    min,
+                    max,
+                    ticks
+                );
+                assert!(((tick / step).round() - tick / step).abs() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn log_y_ticks_within_a_decade() {
+        assert_ticks(
+            &stox_get_chart_log_y_ticks(15.0, 95.0, 6),
+            &[20.0, 30.0, 50.0],
+        );
+    }
+
+    #[test]
+    fn log_y_ticks_over_several_decades() {
+        assert_ticks(
+            &stox_get_chart_log_y_ticks(0.5, 2000.0, 6),
+            &[1.0, 10.0, 100.0, 1000.0],
+        );
+        assert_ticks(
+            &stox_get_chart_log_y_ticks(0.5, 2_000_000.0, 4),
+            &[1.0, 1000.0, 1_000_000.0],
+        );
+    }
+
+    #[test]
+    fn log_y_ticks_keep_to_max_ticks() {
+        for (min, max) in RANGES {
+            for max_ticks in 2..12 {
+                let ticks = stox_get_chart_log_y_ticks(min, max, max_ticks);
+
+                assert!(!ticks.is_empty(), "{} to {}", min, max);
+                assert!(ticks.len() <= max_ticks, "{} to {}: {:?}", min, max, ticks);
+                assert!(ticks.iter().all(|tick| (min..=max).contains(tick)));
+            }
+        }
+    }
+
+    #[test]
+    fn log_y_ticks_of_ranges_without_a_log_scale() {
+        for (min, max) in [(0.0, 100.0), (-5.0, 20.0), (152.59, 152.59)] {
+            let ticks = stox_get_chart_log_y_ticks(min, max, 6);
+
+            assert!(!ticks.is_empty(), "{} to {}", min, max);
+            assert!(ticks.iter().all(|tick| tick.is_finite()), "{:?}", ticks);
+        }
+    }
+
+    /// The wall-clock time of a bar every `step` minutes from `start`, at an
+    /// exchange `utc_offset` seconds ahead of UTC.
+    fn bar_times(start: i64, step: i64, count: i64, utc_offset: i32) -> Vec<NaiveDateTime> {
+        let offset = FixedOffset::east_opt(utc_offset).unwrap();
+
+        (0..count)
+            .map(|index| {
+                Utc.timestamp_opt(start + index * step * 60, 0)
+                    .unwrap()
+                    .with_timezone(&offset)
+                    .naive_local()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn x_ticks_of_a_trading_day() {
+        // 2 minute bars from 9:30 to 15:58
+        let times = bar_times(1678095000, 2, 195, 0);
+        let ticks = stox_get_chart_x_ticks(&times, 0..times.len(), 12);
+
+        assert_eq!(ticks.len(), 12);
+        for (index, label) in ticks {
+            assert_eq!(label, ChartTimeLabel::Time);
+            assert_eq!(times[index].minute() % 30, 0);
+        }
+    }
+
+    #[test]
+    fn x_ticks_keep_to_max_ticks() {
+        // Monthly bars over a century have more decades than fit
+        let times = bar_times(-2208988800, 30 * 24 * 60, 1217, 0);
+
+        for max_ticks in 0..12 {
+            let ticks = stox_get_chart_x_ticks(&times, 0..times.len(), max_ticks);
+
+            assert!(ticks.len() <= max_ticks, "{}: {:?}", max_ticks, ticks);
+            assert_eq!(ticks.is_empty(), max_ticks == 0, "{}", max_ticks);
+        }
+    }
+
+    #[test]
+    fn x_ticks_of_months_across_a_new_year() {
+        // The weekdays from October 3rd, 2022 to March 31st, 2023
+        let times: Vec<NaiveDateTime> = bar_times(1664755200, 24 * 60, 180, 0)
+            .into_iter()
+            .filter(|time| time.weekday().num_days_from_monday() < 5)
+            .collect();
+        let ticks = stox_get_chart_x_ticks(&times, 0..times.len(), 8);
+
+        let labels: Vec<(u32, ChartTimeLabel)> = ticks
+            .into_iter()
+            .map(|(index, label)| (times[index].month(), label))
+            .collect();
+        assert_eq!(
+            labels,
+            [
+                (11, ChartTimeLabel::Month),
+                (12, ChartTimeLabel::Month),
+                (1, ChartTimeLabel::Year),
+                (2, ChartTimeLabel::Month),
+                (3, ChartTimeLabel::Month),
+            ]
+        );
+    }
+
+    #[test]
+    fn x_ticks_start_days_in_the_exchange_time_zone() {
+        // Hourly bars from 4:00 to 19:00 New York time (UTC-5) on two days,
+        // the evening bars fall on the next day in UTC
+        let times: Vec<NaiveDateTime> = bar_times(1678093200, 60, 40, -5 * 60 * 60)
+            .into_iter()
+            .filter(|time| (4..20).contains(&time.hour()))
+            .collect();
+        let ticks = stox_get_chart_x_ticks(&times, 0..times.len(), 1);
+
+        assert_eq!(ticks, [(16, ChartTimeLabel::Day)]);
+        assert_eq!(times[16].hour(), 4);
+        assert_eq!(times[16].day(), 7);
+    }
+
+    #[test]
+    fn x_ticks_only_start_in_view() {
+        let times = bar_times(1678095000, 2, 195, 0);
+        let ticks = stox_get_chart_x_ticks(&times, 100..150, 100);
+
+        assert!(ticks.iter().all(|(index, _)| (100..150).contains(index)));
+    }
 }
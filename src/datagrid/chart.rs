@@ -0,0 +1,1246 @@
+use std::cell::RefCell;
+use std::fs::File;
+use std::path::Path;
+
+use chrono::prelude::*;
+
+use gtk4::cairo::{self, Context};
+use gtk4::glib;
+
+use yahoo_finance_api::Quote;
+
+use crate::data_helper::{
+    stox_get_chart_log_y_ticks, stox_get_chart_x_ticks, stox_get_chart_y_ticks, ChartInfo,
+    MarketSession,
+};
+use crate::format_helper::{
+    fmt_change_percent, fmt_chart_time, fmt_number, fmt_percent_tick, fmt_price_tick, MISSING,
+};
+use crate::indicators::{Indicator, IndicatorSeries};
+
+const BACKGROUND_COLOR: (f64, f64, f64) = (56.0 / 255.0, 56.0 / 255.0, 56.0 / 255.0);
+const GRID_COLOR: (f64, f64, f64) = (1.0, 1.0, 1.0);
+const UP_COLOR: (f64, f64, f64) = (0.0, 1.0, 0.0);
+const DOWN_COLOR: (f64, f64, f64) = (1.0, 0.0, 0.0);
+
+/// Room left under the plot for the x-axis labels
+const X_AXIS_HEIGHT: f64 = 15.0;
+/// Room kept above and below the plotted prices
+const PLOT_PADDING: f64 = 5.0;
+/// How much of its slot a candle body or volume bar takes up
+const CANDLE_WIDTH: f64 = 0.7;
+/// How much of the height above any oscillators the volume pane takes up,
+/// under the prices
+const VOLUME_PANE_RATIO: f64 = 0.2;
+/// How much of the height each oscillator pane takes up, under the volume
+const OSCILLATOR_PANE_RATIO: f64 = 0.2;
+/// However many oscillators there are, they leave this much of the height
+/// to the rest
+const MAX_OSCILLATOR_RATIO: f64 = 0.5;
+const AREA_ALPHA: f64 = 0.3;
+const CROSSHAIR_COLOR: (f64, f64, f64) = (0.8, 0.8, 0.8);
+const CROSSHAIR_DASH: f64 = 4.0;
+const SELECTION_COLOR: (f64, f64, f64, f64) = (0.5, 0.7, 1.0, 0.25);
+/// Tints the background behind pre-market bars blue
+const PRE_MARKET_COLOR: (f64, f64, f64, f64) = (0.3, 0.5, 1.0, 0.15);
+/// Darkens the background behind post-market bars
+const POST_MARKET_COLOR: (f64, f64, f64, f64) = (0.0, 0.0, 0.0, 0.3);
+const PREVIOUS_CLOSE_COLOR: (f64, f64, f64) = (1.0, 0.8, 0.0);
+const PREVIOUS_CLOSE_DASH: f64 = 6.0;
+/// Handed out to the indicators in the order they were added
+const INDICATOR_COLORS: [(f64, f64, f64); 5] = [
+    (0.0, 0.8, 1.0),
+    (1.0, 0.5, 0.0),
+    (0.8, 0.4, 1.0),
+    (1.0, 0.4, 0.7),
+    (0.6, 0.9, 0.9),
+];
+/// Handed out to the compared symbols in the order they were added
+const COMPARISON_COLORS: [(f64, f64, f64); 4] = [
+    (0.35, 0.55, 1.0),
+    (1.0, 1.0, 0.5),
+    (1.0, 0.3, 1.0),
+    (0.8, 0.6, 0.4),
+];
+/// Room between the entries of the legend and around it
+const LEGEND_SPACING: f64 = 8.0;
+/// The second line of an oscillator, e.g. the MACD signal line or %D
+const SIGNAL_COLOR: (f64, f64, f64) = (1.0, 0.85, 0.3);
+const HISTOGRAM_ALPHA: f64 = 0.5;
+const LEVEL_DASH: f64 = 2.0;
+/// The least room between two x-axis ticks
+const X_TICK_SPACING: f64 = 80.0;
+/// The least room between the end of an x-axis label and the next one
+const X_LABEL_GAP: f64 = 6.0;
+/// The least room between two y-axis ticks
+const Y_TICK_SPACING: f64 = 50.0;
+
+/// Zooming in stops once this few bars are left
+const MIN_VISIBLE_BARS: usize = 5;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChartStyle {
+    #[default]
+    Line,
+    Area,
+    Candle,
+}
+
+/// The file formats a chart can be exported to.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Png,
+    Svg,
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Svg => "svg",
+        }
+    }
+}
+
+/// The bars in view, from `start` up to but not including `end`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Viewport {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Viewport {
+    /// A viewport from `start` to `end` in any order, kept within `count` bars
+    /// and at least `MIN_VISIBLE_BARS` wide.
+    pub fn new(start: usize, end: usize, count: usize) -> Self {
+        let (start, end) = (start.min(end).min(count), start.max(end).min(count));
+        let len = (end - start).max(MIN_VISIBLE_BARS).min(count);
+
+        Self {
+            start: start.min(count - len),
+            end: start.min(count - len) + len,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn contains(&self, index: usize) -> bool {
+        (self.start..self.end).contains(&index)
+    }
+
+    /// Scales the viewport by `factor`, keeping `anchor` where it is on
+    /// screen. Factors below 1 zoom in.
+    pub fn zoom(&self, factor: f64, anchor: usize, count: usize) -> Self {
+        let anchor = anchor.clamp(self.start, self.end);
+        let len = ((self.len() as f64 * factor).round() as usize).max(MIN_VISIBLE_BARS);
+        let before = (anchor - self.start) as f64 / self.len() as f64;
+        let start = anchor.saturating_sub((before * len as f64).round() as usize);
+
+        Self::new(start, start.saturating_add(len), count)
+    }
+
+    /// Moves the viewport by `bars`, later in time when positive.
+    pub fn pan(&self, bars: isize, count: usize) -> Self {
+        let start = self
+            .start
+            .saturating_add_signed(bars)
+            .min(count.saturating_sub(self.len()));
+
+        Self::new(start, start + self.len(), count)
+    }
+}
+
+/// How a chart is looked at, as opposed to the data it shows.
+#[derive(Clone, Copy, Default)]
+pub struct ChartView {
+    pub style: ChartStyle,
+    /// The index of the bar under the crosshair
+    pub crosshair: Option<usize>,
+    /// Every bar is shown when there is no viewport
+    pub viewport: Option<Viewport>,
+    /// The x coordinates of a window being dragged out to zoom into
+    pub selection: Option<(f64, f64)>,
+    /// Times are shown in the exchange's time zone unless this is set
+    pub local_time: bool,
+    /// Plots prices so that equal percent changes take up the same height
+    pub log_scale: bool,
+}
+
+/// Where the parts of a chart go for a given size.
+struct Layout {
+    width: f64,
+    height: f64,
+    /// The prices are plotted between `top` and `bottom`
+    top: f64,
+    bottom: f64,
+    /// The volume pane spans from `volume_top` to `volume_bottom`, it has no
+    /// height when there is no volume
+    volume_top: f64,
+    volume_bottom: f64,
+    /// The oscillator panes are stacked from `volume_bottom` down to
+    /// `plot_bottom`
+    pane_height: f64,
+    plot_bottom: f64,
+    viewport: Viewport,
+    log_scale: bool,
+}
+
+impl Layout {
+    fn slot_width(&self) -> f64 {
+        self.width / self.viewport.len() as f64
+    }
+
+    fn bar_width(&self) -> f64 {
+        (self.slot_width() * CANDLE_WIDTH).max(1.0)
+    }
+
+    /// Bars in view are spread evenly over the width, each centered in its
+    /// own slot.
+    fn bar_x(&self, index: usize) -> f64 {
+        (index as f64 - self.viewport.start as f64 + 0.5) * self.slot_width()
+    }
+
+    fn bar_at(&self, x: f64) -> usize {
+        let offset = (x / self.slot_width()).floor().max(0.0) as usize;
+
+        (self.viewport.start + offset).min(self.viewport.end - 1)
+    }
+
+    /// The top and bottom of the oscillator pane at `index`.
+    fn pane(&self, index: usize) -> (f64, f64) {
+        let top = self.volume_bottom + index as f64 * self.pane_height;
+
+        (top, top + self.pane_height)
+    }
+}
+
+/// Maps prices onto the y coordinates of the plot, highest at the top.
+struct PriceScale {
+    min: f64,
+    max: f64,
+    top: f64,
+    bottom: f64,
+    /// Only ever set for positive prices
+    log: bool,
+}
+
+impl PriceScale {
+    fn new(prices: impl Iterator<Item = f64>, top: f64, bottom: f64) -> Self {
+        let (min, max) = prices.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), price| {
+            (min.min(price), max.max(price))
+        });
+
+        Self {
+            min,
+            max,
+            top,
+            bottom,
+            log: false,
+        }
+    }
+
+    /// The same scale on a log scale, unless there are prices of zero or
+    /// below, e.g. for some futures.
+    fn logarithmic(self) -> Self {
+        Self {
+            log: self.min > 0.0,
+            ..self
+        }
+    }
+
+    fn y(&self, price: f64) -> f64 {
+        if self.max <= self.min {
+            // A flat series sits in the middle
+            return (self.top + self.bottom) / 2.0;
+        }
+
+        let fraction = if self.log {
+            (price / self.min).ln() / (self.max / self.min).ln()
+        } else {
+            (price - self.min) / (self.max - self.min)
+        };
+
+        self.bottom - fraction * (self.bottom - self.top)
+    }
+}
+
+/// An indicator, worked out once for every bar.
+struct Overlay {
+    indicator: Indicator,
+    name: String,
+    color: (f64, f64, f64),
+    series: IndicatorSeries,
+}
+
+/// Another symbol drawn over the prices.
+struct Comparison {
+    symbol: String,
+    /// The last close of the symbol at or before each bar of the chart
+    closes: Vec<Option<f64>>,
+}
+
+/// One chart range, ready to be drawn on any cairo context.
+pub struct Chart {
+    symbol: String,
+    info: ChartInfo,
+    exchange_time_zone: glib::TimeZone,
+    overlays: RefCell<Vec<Overlay>>,
+    comparisons: RefCell<Vec<Comparison>>,
+}
+
+impl Chart {
+    pub fn new(symbol: &str, info: ChartInfo) -> Self {
+        Self {
+            symbol: symbol.to_owned(),
+            exchange_time_zone: info.exchange_time_zone(),
+            info,
+            overlays: RefCell::new(vec![]),
+            comparisons: RefCell::new(vec![]),
+        }
+    }
+
+    /// Draws the closes of `symbol` over the prices, both as the percent
+    /// change since the first bar in view.
+    pub fn add_comparison(&self, symbol: &str, info: &ChartInfo) {
+        // The symbols need not trade at the same times, e.g. on different
+        // exchanges, so each bar gets the last close there was by then
+        let mut quotes = info.quotes.iter().peekable();
+        let mut close = None;
+        let closes = self
+            .info
+            .quotes
+            .iter()
+            .map(|quote| {
+                while let Some(other) = quotes.next_if(|other| other.timestamp <= quote.timestamp) {
+                    close = Some(other.close);
+                }
+                close
+            })
+            .collect();
+
+        let mut comparisons = self.comparisons.borrow_mut();
+        comparisons.retain(|comparison| comparison.symbol != symbol);
+        comparisons.push(Comparison {
+            symbol: symbol.to_owned(),
+            closes,
+        });
+    }
+
+    /// Leaves out the compared symbols not in `symbols`, and puts the others
+    /// in the same order so they keep their colors across charts.
+    pub fn retain_comparisons(&self, symbols: &[String]) {
+        let mut comparisons = self.comparisons.borrow_mut();
+
+        comparisons.retain(|comparison| symbols.contains(&comparison.symbol));
+        comparisons.sort_by_key(|comparison| {
+            symbols
+                .iter()
+                .position(|symbol| *symbol == comparison.symbol)
+        });
+    }
+
+    /// Works out the lines of the indicators drawn over the prices.
+    pub fn set_indicators(&self, indicators: &[Indicator]) {
+        // VWAP starts over every day on intraday charts, the day being the
+        // exchange's
+        let days: Vec<_> = (0..self.bar_count())
+            .map(|index| {
+                self.time(index, &ChartView::default())
+                    .map(|time| (time.year(), time.day_of_year()))
+            })
+            .collect();
+        let intraday = !self.info.trading_periods.is_empty();
+        let new_session = |index: usize| intraday && index > 0 && days[index] != days[index - 1];
+
+        let overlays = indicators
+            .iter()
+            .zip(INDICATOR_COLORS.iter().cycle())
+            .map(|(indicator, color)| Overlay {
+                indicator: *indicator,
+                name: indicator.name(),
+                color: *color,
+                series: indicator.series(&self.info.quotes, new_session),
+            })
+            .collect();
+
+        self.overlays.replace(overlays);
+    }
+
+    pub fn draw(
+        &self,
+        cr: &Context,
+        width: i32,
+        height: i32,
+        view: &ChartView,
+    ) -> Result<(), cairo::Error> {
+        let layout = self.layout(width, height, view);
+        let compared = self.compared_prices(&layout);
+        let scale = self.price_scale(view.style, &layout, &compared);
+
+        set_source_color(cr, BACKGROUND_COLOR);
+        cr.paint()?;
+        cr.set_line_width(1.0);
+
+        self.draw_extended_hours(cr, &layout)?;
+
+        match view.style {
+            ChartStyle::Line => self.draw_line(cr, &layout, &scale, false)?,
+            ChartStyle::Area => self.draw_line(cr, &layout, &scale, true)?,
+            ChartStyle::Candle => self.draw_candles(cr, &layout, &scale)?,
+        }
+
+        if layout.volume_top < layout.volume_bottom {
+            self.draw_volume(cr, &layout)?;
+        }
+
+        self.draw_overlays(cr, &layout, &scale)?;
+        draw_comparisons(cr, &layout, &scale, &compared)?;
+        self.draw_panes(cr, &layout, view)?;
+        self.draw_grid(cr, &layout, view, &scale)?;
+        self.draw_legend(cr, &layout, view, &compared)?;
+
+        if let Some(previous_close) = self.info.previous_close {
+            self.draw_previous_close(cr, &layout, &scale, previous_close)?;
+        }
+
+        if let Some(index) = view.crosshair {
+            self.draw_crosshair(cr, &layout, &scale, index)?;
+        }
+
+        if let Some(selection) = view.selection {
+            draw_selection(cr, &layout, selection)?;
+        }
+
+        Ok(())
+    }
+
+    /// Draws the chart as seen through `view` into a new file at `path`,
+    /// `width` by `height` pixels, or points for SVG. The crosshair and a
+    /// selection being dragged out are left out.
+    pub fn export(
+        &self,
+        path: &Path,
+        format: ExportFormat,
+        width: i32,
+        height: i32,
+        view: &ChartView,
+    ) -> Result<(), cairo::IoError> {
+        let view = ChartView {
+            crosshair: None,
+            selection: None,
+            ..*view
+        };
+
+        match format {
+            ExportFormat::Png => {
+                let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, width, height)?;
+                self.draw(&Context::new(&surface)?, width, height, &view)?;
+
+                surface.write_to_png(&mut File::create(path)?)
+            }
+            ExportFormat::Svg => {
+                let surface = cairo::SvgSurface::new(width as f64, height as f64, Some(path))?;
+                self.draw(&Context::new(&surface)?, width, height, &view)?;
+
+                // Nothing is written out until the surface is finished
+                surface.finish();
+                Ok(surface.status()?)
+            }
+        }
+    }
+
+    pub fn symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    pub fn bar_count(&self) -> usize {
+        self.info.quotes.len()
+    }
+
+    pub fn quote(&self, index: usize) -> Option<&Quote> {
+        self.info.quotes.get(index)
+    }
+
+    /// When a bar starts, at the exchange or on this computer.
+    pub fn time(&self, index: usize, view: &ChartView) -> Option<glib::DateTime> {
+        let time = glib::DateTime::from_unix_utc(self.quote(index)?.timestamp as i64).ok()?;
+
+        if view.local_time {
+            time.to_local().ok()
+        } else {
+            time.to_timezone(&self.exchange_time_zone).ok()
+        }
+    }
+
+    /// The bars in view, all of them unless zoomed in.
+    pub fn visible(&self, view: &ChartView) -> Viewport {
+        view.viewport.unwrap_or(Viewport {
+            start: 0,
+            end: self.bar_count(),
+        })
+    }
+
+    /// The bar in view closest to `x` on a chart `width` wide.
+    pub fn bar_at(&self, x: f64, width: i32, view: &ChartView) -> usize {
+        self.layout(width, 0, view).bar_at(x)
+    }
+
+    pub fn bar_x(&self, index: usize, width: i32, view: &ChartView) -> f64 {
+        self.layout(width, 0, view).bar_x(index)
+    }
+
+    /// How many bars fit in a pixel, used to pan along with the pointer.
+    pub fn bars_per_pixel(&self, width: i32, view: &ChartView) -> f64 {
+        1.0 / self.layout(width, 0, view).slot_width()
+    }
+
+    fn visible_quotes(&self, layout: &Layout) -> impl Iterator<Item = (usize, &Quote)> {
+        let viewport = layout.viewport;
+
+        self.info
+            .quotes
+            .iter()
+            .enumerate()
+            .skip(viewport.start)
+            .take(viewport.len())
+    }
+
+    fn layout(&self, width: i32, height: i32, view: &ChartView) -> Layout {
+        let width = width as f64;
+        let height = height as f64;
+
+        let plot_bottom = height - X_AXIS_HEIGHT;
+
+        let pane_count = self
+            .overlays
+            .borrow()
+            .iter()
+            .filter(|overlay| overlay.indicator.is_oscillator())
+            .count();
+        let pane_height = if pane_count > 0 {
+            let max_pane_height = plot_bottom * MAX_OSCILLATOR_RATIO / pane_count as f64;
+            (plot_bottom * OSCILLATOR_PANE_RATIO)
+                .min(max_pane_height)
+                .round()
+        } else {
+            0.0
+        };
+
+        let volume_bottom = plot_bottom - pane_height * pane_count as f64;
+        let volume_top = if self.has_volume() {
+            volume_bottom - (volume_bottom * VOLUME_PANE_RATIO).round()
+        } else {
+            volume_bottom
+        };
+
+        Layout {
+            width,
+            height,
+            top: PLOT_PADDING,
+            bottom: volume_top - PLOT_PADDING,
+            volume_top,
+            volume_bottom,
+            pane_height,
+            plot_bottom,
+            viewport: self.visible(view),
+            log_scale: view.log_scale,
+        }
+    }
+
+    /// Candles need room for the whole range of every bar in view, lines only
+    /// for the closes. The `compared` prices are kept in view as well.
+    fn price_scale(
+        &self,
+        style: ChartStyle,
+        layout: &Layout,
+        compared: &[Vec<Option<f64>>],
+    ) -> PriceScale {
+        let quotes = self.visible_quotes(layout).map(|(_, quote)| quote);
+        let prices: Vec<f64> = match style {
+            ChartStyle::Candle => quotes.flat_map(|quote| [quote.low, quote.high]).collect(),
+            _ => quotes.map(|quote| quote.close).collect(),
+        };
+
+        // The previous close and the indicators are kept in view too
+        let viewport = layout.viewport;
+        let overlays = self.overlays.borrow();
+        let overlay_prices = overlays
+            .iter()
+            .filter(|overlay| !overlay.indicator.is_oscillator())
+            .flat_map(|overlay| &overlay.series.lines)
+            .flat_map(|line| line[viewport.start..viewport.end].iter().flatten());
+
+        let compared_prices = compared
+            .iter()
+            .flat_map(|line| line[viewport.start..viewport.end].iter().flatten());
+
+        let scale = PriceScale::new(
+            prices
+                .into_iter()
+                .chain(self.info.previous_close)
+                .chain(overlay_prices.copied())
+                .chain(compared_prices.copied()),
+            layout.top,
+            layout.bottom,
+        );
+
+        if layout.log_scale {
+            scale.logarithmic()
+        } else {
+            scale
+        }
+    }
+
+    /// The close the percent changes are worked out from when comparing with
+    /// other symbols, that of the first bar in view.
+    fn comparison_base(&self, layout: &Layout) -> Option<f64> {
+        if self.comparisons.borrow().is_empty() {
+            return None;
+        }
+
+        self.quote(layout.viewport.start).map(|quote| quote.close)
+    }
+
+    /// The closes of the compared symbols moved onto the prices of the chart,
+    /// so that they change by the same percentage from the first bar in view.
+    fn compared_prices(&self, layout: &Layout) -> Vec<Vec<Option<f64>>> {
+        let Some(base) = self.comparison_base(layout) else {
+            return vec![];
+        };
+        let viewport = layout.viewport;
+
+        self.comparisons
+            .borrow()
+            .iter()
+            .map(|comparison| {
+                // A symbol that only starts trading later in view starts
+                // from its first close instead
+                let first_close = comparison.closes[viewport.start..viewport.end]
+                    .iter()
+                    .flatten()
+                    .next();
+
+                comparison
+                    .closes
+                    .iter()
+                    .map(|close| Some(base * close.as_ref()? / first_close?))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Currencies and some indices trade without any reported volume.
+    fn has_volume(&self) -> bool {
+        self.info.quotes.iter().any(|quote| quote.volume > 0)
+    }
+
+    /// Shades the slots of pre and post-market bars, each session in its own
+    /// shade, all the way down through the volume pane.
+    fn draw_extended_hours(&self, cr: &Context, layout: &Layout) -> Result<(), cairo::Error> {
+        let slot_width = layout.slot_width();
+
+        for (session, color) in [
+            (MarketSession::Pre, PRE_MARKET_COLOR),
+            (MarketSession::Post, POST_MARKET_COLOR),
+        ] {
+            for (i, quote) in self.visible_quotes(layout) {
+                if self.info.session_at(quote.timestamp) == Some(session) {
+                    let x = layout.bar_x(i) - slot_width / 2.0;
+                    cr.rectangle(x, 0.0, slot_width, layout.plot_bottom);
+                }
+            }
+
+            // Filled at once so that neighbouring slots don't leave seams
+            let (r, g, b, a) = color;
+            cr.set_source_rgba(r, g, b, a);
+            cr.fill()?;
+        }
+
+        Ok(())
+    }
+
+    fn draw_line(
+        &self,
+        cr: &Context,
+        layout: &Layout,
+        scale: &PriceScale,
+        filled: bool,
+    ) -> Result<(), cairo::Error> {
+        for (i, quote) in self.visible_quotes(layout) {
+            cr.line_to(layout.bar_x(i), scale.y(quote.close));
+        }
+
+        let color = if self.info.change_neg() {
+            DOWN_COLOR
+        } else {
+            UP_COLOR
+        };
+
+        if filled {
+            let path = cr.copy_path()?;
+
+            cr.line_to(layout.bar_x(layout.viewport.end - 1), layout.bottom);
+            cr.line_to(layout.bar_x(layout.viewport.start), layout.bottom);
+            cr.close_path();
+            cr.set_source_rgba(color.0, color.1, color.2, AREA_ALPHA);
+            cr.fill()?;
+
+            cr.append_path(&path);
+        }
+
+        set_source_color(cr, color);
+        cr.stroke()
+    }
+
+    fn draw_candles(
+        &self,
+        cr: &Context,
+        layout: &Layout,
+        scale: &PriceScale,
+    ) -> Result<(), cairo::Error> {
+        let body_width = layout.bar_width();
+
+        for (i, quote) in self.visible_quotes(layout) {
+            let x = layout.bar_x(i);
+
+            set_source_color(cr, bar_color(quote));
+
+            // The wick spans the whole range of the bar
+            cr.move_to(x, scale.y(quote.high));
+            cr.line_to(x, scale.y(quote.low));
+            cr.stroke()?;
+
+            let body_top = scale.y(quote.open.max(quote.close));
+            let body_bottom = scale.y(quote.open.min(quote.close));
+            cr.rectangle(
+                x - body_width / 2.0,
+                body_top,
+                body_width,
+                (body_bottom - body_top).max(1.0),
+            );
+            cr.fill()?;
+        }
+
+        Ok(())
+    }
+
+    /// Draws a bar per period growing up from the bottom of the pane, scaled
+    /// to the period in view with the most volume.
+    fn draw_volume(&self, cr: &Context, layout: &Layout) -> Result<(), cairo::Error> {
+        let max_volume = self
+            .visible_quotes(layout)
+            .map(|(_, quote)| quote.volume)
+            .max()
+            .unwrap_or(0)
+            .max(1) as f64;
+        let bar_width = layout.bar_width();
+        let pane_height = layout.volume_bottom - layout.volume_top - PLOT_PADDING;
+
+        for (i, quote) in self.visible_quotes(layout) {
+            let bar_height = quote.volume as f64 / max_volume * pane_height;
+
+            set_source_color(cr, bar_color(quote));
+            cr.rectangle(
+                layout.bar_x(i) - bar_width / 2.0,
+                layout.volume_bottom - bar_height,
+                bar_width,
+                bar_height,
+            );
+            cr.fill()?;
+        }
+
+        Ok(())
+    }
+
+    fn draw_grid(
+        &self,
+        cr: &Context,
+        layout: &Layout,
+        view: &ChartView,
+        scale: &PriceScale,
+    ) -> Result<(), cairo::Error> {
+        let width = layout.width;
+        let height = layout.height;
+        let volume_top = layout.volume_top;
+
+        set_source_color(cr, GRID_COLOR);
+
+        // x-axis (horizontal) lines, where a new minute, hour, day... starts.
+        // The step depends on how much time is in view. Only the bars in view
+        // and the one before them are compared
+        let viewport = layout.viewport;
+        let first = viewport.start.saturating_sub(1);
+        let times: Vec<NaiveDateTime> = (first..viewport.end)
+            .map(|index| {
+                self.time(index, view)
+                    .and_then(|time| wall_clock(&time))
+                    .unwrap_or_default()
+            })
+            .collect();
+        let visible = viewport.start - first..viewport.end - first;
+        let max_ticks = (width / X_TICK_SPACING) as usize + 1;
+
+        let mut previous_label_end = f64::MIN;
+        for (index, label) in stox_get_chart_x_ticks(&times, visible, max_ticks) {
+            let index = first + index;
+            let Some(time) = self.time(index, view) else {
+                continue;
+            };
+            let x_grid_line = layout.bar_x(index).round();
+
+            // Ticks are only evenly spread on average, a label that would
+            // run into the one before it is left out
+            if x_grid_line < previous_label_end + X_LABEL_GAP {
+                continue;
+            }
+
+            cr.move_to(x_grid_line, height - X_AXIS_HEIGHT);
+            cr.line_to(x_grid_line, 0.0);
+            cr.stroke()?;
+
+            let label = fmt_chart_time(&time, label);
+            cr.move_to(x_grid_line + 2.0, height - 3.0);
+            cr.show_text(&label)?;
+            previous_label_end = x_grid_line + 2.0 + cr.text_extents(&label)?.x_advance();
+        }
+
+        // Separates the volume pane from the prices, and the oscillator panes
+        // from the volume and each other
+        cr.move_to(0.0, volume_top);
+        cr.line_to(width, volume_top);
+        let mut pane_top = layout.volume_bottom;
+        while pane_top < layout.plot_bottom {
+            cr.move_to(0.0, pane_top);
+            cr.line_to(width, pane_top);
+            pane_top += layout.pane_height;
+        }
+        cr.stroke()?;
+
+        // y-axis (vertical) lines, at round prices within what is plotted, or
+        // at round percent changes when comparing with other symbols. The
+        // ticks are picked as multiples of the price at the first bar then,
+        // which are just as round
+        let base = self.comparison_base(layout);
+        let multiple = base.unwrap_or(1.0);
+        let (min, max) = (scale.min / multiple, scale.max / multiple);
+        let max_ticks = ((layout.bottom - layout.top) / Y_TICK_SPACING) as usize + 1;
+        let ticks = if scale.log {
+            stox_get_chart_log_y_ticks(min, max, max_ticks)
+        } else {
+            stox_get_chart_y_ticks(min, max, max_ticks)
+        };
+
+        for (index, tick) in ticks.iter().enumerate() {
+            // Log scale ticks are spread unevenly, and need as many decimals
+            // as the closest one next to them
+            let step = [index.checked_sub(1), Some(index + 1)]
+                .into_iter()
+                .flatten()
+                .filter_map(|other| Some((ticks.get(other)? - tick).abs()))
+                .reduce(f64::min)
+                .unwrap_or(max - min);
+
+            let label = match base {
+                Some(_) => fmt_percent_tick((tick - 1.0) * 100.0, step * 100.0),
+                None => fmt_price_tick(*tick, step, &self.info.currency),
+            };
+            let y_grid_line = scale.y(tick * multiple).round();
+
+            cr.move_to(0.0, y_grid_line);
+            cr.line_to(width, y_grid_line);
+            cr.stroke()?;
+
+            cr.move_to(2.0, y_grid_line - 2.0);
+            cr.show_text(&label)?;
+        }
+
+        Ok(())
+    }
+
+    fn draw_overlays(
+        &self,
+        cr: &Context,
+        layout: &Layout,
+        scale: &PriceScale,
+    ) -> Result<(), cairo::Error> {
+        for overlay in self.overlays.borrow().iter() {
+            if overlay.indicator.is_oscillator() {
+                continue;
+            }
+
+            for line in &overlay.series.lines {
+                trace_line(cr, layout, scale, line);
+                set_source_color(cr, overlay.color);
+                cr.stroke()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Every oscillator in a pane of its own under the volume, named along
+    /// with its value under the crosshair or else at the last bar.
+    fn draw_panes(
+        &self,
+        cr: &Context,
+        layout: &Layout,
+        view: &ChartView,
+    ) -> Result<(), cairo::Error> {
+        let viewport = layout.viewport;
+        let index = view
+            .crosshair
+            .filter(|index| viewport.contains(*index))
+            .unwrap_or(viewport.end - 1);
+
+        let overlays = self.overlays.borrow();
+        let oscillators = overlays
+            .iter()
+            .filter(|overlay| overlay.indicator.is_oscillator());
+
+        for (pane, overlay) in oscillators.enumerate() {
+            let (top, bottom) = layout.pane(pane);
+            let series = &overlay.series;
+            let levels = overlay.indicator.levels();
+
+            let top = top + PLOT_PADDING;
+            let bottom = bottom - PLOT_PADDING;
+            let scale = match overlay.indicator.bounds() {
+                Some((min, max)) => PriceScale {
+                    min,
+                    max,
+                    top,
+                    bottom,
+                    log: false,
+                },
+                None => {
+                    let values = series
+                        .lines
+                        .iter()
+                        .chain(&series.histogram)
+                        .flat_map(|line| line[viewport.start..viewport.end].iter().flatten());
+
+                    PriceScale::new(values.chain(levels).copied(), top, bottom)
+                }
+            };
+
+            cr.save()?;
+            set_source_color(cr, GRID_COLOR);
+            cr.set_dash(&[LEVEL_DASH], 0.0);
+            for level in levels {
+                let y = scale.y(*level).round() + 0.5;
+                cr.move_to(0.0, y);
+                cr.line_to(layout.width, y);
+            }
+            cr.stroke()?;
+            cr.restore()?;
+
+            if let Some(histogram) = &series.histogram {
+                let bar_width = layout.bar_width();
+                let visible = histogram[viewport.start..viewport.end].iter();
+
+                for (i, value) in (viewport.start..).zip(visible) {
+                    let Some(value) = value else {
+                        continue;
+                    };
+                    let (red, green, blue) = if *value < 0.0 { DOWN_COLOR } else { UP_COLOR };
+
+                    cr.set_source_rgba(red, green, blue, HISTOGRAM_ALPHA);
+                    cr.rectangle(
+                        layout.bar_x(i) - bar_width / 2.0,
+                        scale.y(0.0),
+                        bar_width,
+                        scale.y(*value) - scale.y(0.0),
+                    );
+                    cr.fill()?;
+                }
+            }
+
+            for (line_index, line) in series.lines.iter().enumerate() {
+                trace_line(cr, layout, &scale, line);
+                set_source_color(
+                    cr,
+                    if line_index == 0 {
+                        overlay.color
+                    } else {
+                        SIGNAL_COLOR
+                    },
+                );
+                cr.stroke()?;
+            }
+
+            let label = format!(
+                "{}  {}",
+                overlay.name,
+                fmt_number(series.lines.first().and_then(|line| line[index]))
+            );
+            set_source_color(cr, overlay.color);
+            cr.move_to(2.0, top + LEGEND_SPACING);
+            cr.show_text(&label)?;
+        }
+
+        Ok(())
+    }
+
+    /// The names of what is drawn over the prices in their colors, in the top
+    /// right corner. Compared symbols come with their change up to the
+    /// crosshair or else the last bar.
+    fn draw_legend(
+        &self,
+        cr: &Context,
+        layout: &Layout,
+        view: &ChartView,
+        compared: &[Vec<Option<f64>>],
+    ) -> Result<(), cairo::Error> {
+        let mut entries = vec![];
+
+        if let Some(base) = self.comparison_base(layout) {
+            let viewport = layout.viewport;
+            let index = view
+                .crosshair
+                .filter(|index| viewport.contains(*index))
+                .unwrap_or(viewport.end - 1);
+            let entry = |symbol: &str, price: Option<f64>| {
+                let change = price.map_or_else(
+                    || MISSING.to_owned(),
+                    |price| fmt_change_percent(percent_change(price, base)),
+                );
+                format!("{}  {}", symbol, change)
+            };
+
+            let color = if self.info.change_neg() {
+                DOWN_COLOR
+            } else {
+                UP_COLOR
+            };
+            let close = self.quote(index).map(|quote| quote.close);
+            entries.push((entry(&self.symbol, close), color));
+
+            let comparisons = self.comparisons.borrow();
+            for ((comparison, line), color) in comparisons
+                .iter()
+                .zip(compared)
+                .zip(COMPARISON_COLORS.iter().cycle())
+            {
+                entries.push((entry(&comparison.symbol, line[index]), *color));
+            }
+        }
+
+        for overlay in self.overlays.borrow().iter() {
+            if !overlay.indicator.is_oscillator() {
+                entries.push((overlay.name.clone(), overlay.color));
+            }
+        }
+
+        let mut x = layout.width;
+        for (label, color) in entries.iter().rev() {
+            x -= cr.text_extents(label)?.x_advance() + LEGEND_SPACING;
+
+            set_source_color(cr, *color);
+            cr.move_to(x, LEGEND_SPACING + layout.top);
+            cr.show_text(label)?;
+        }
+
+        Ok(())
+    }
+
+    /// A dashed line across the plot, labelled at the right end.
+    fn draw_previous_close(
+        &self,
+        cr: &Context,
+        layout: &Layout,
+        scale: &PriceScale,
+        previous_close: f64,
+    ) -> Result<(), cairo::Error> {
+        let y = scale.y(previous_close).round() + 0.5;
+
+        cr.save()?;
+        set_source_color(cr, PREVIOUS_CLOSE_COLOR);
+        cr.set_dash(&[PREVIOUS_CLOSE_DASH], 0.0);
+        cr.move_to(0.0, y);
+        cr.line_to(layout.width, y);
+        cr.stroke()?;
+        cr.restore()?;
+
+        let label = fmt_price_tick(previous_close, 0.01, &self.info.currency);
+        let extents = cr.text_extents(&label)?;
+        set_source_color(cr, PREVIOUS_CLOSE_COLOR);
+        cr.move_to(layout.width - extents.x_advance() - 2.0, y - 3.0);
+        cr.show_text(&label)
+    }
+
+    /// Crosses at the close of the bar, so it follows the line and the
+    /// arrow keys alike.
+    fn draw_crosshair(
+        &self,
+        cr: &Context,
+        layout: &Layout,
+        scale: &PriceScale,
+        index: usize,
+    ) -> Result<(), cairo::Error> {
+        let Some(quote) = self.quote(index) else {
+            return Ok(());
+        };
+        if !layout.viewport.contains(index) {
+            return Ok(());
+        }
+
+        let x = layout.bar_x(index);
+        let y = scale.y(quote.close);
+
+        cr.save()?;
+        set_source_color(cr, CROSSHAIR_COLOR);
+        cr.set_dash(&[CROSSHAIR_DASH], 0.0);
+
+        cr.move_to(x, 0.0);
+        cr.line_to(x, layout.plot_bottom);
+        cr.move_to(0.0, y);
+        cr.line_to(layout.width, y);
+        cr.stroke()?;
+
+        cr.restore()
+    }
+}
+
+/// How much `price` is up or down from `base`, in percent.
+fn percent_change(price: f64, base: f64) -> f64 {
+    (price / base - 1.0) * 100.0
+}
+
+/// Draws the `compared` prices of the other symbols in their colors.
+fn draw_comparisons(
+    cr: &Context,
+    layout: &Layout,
+    scale: &PriceScale,
+    compared: &[Vec<Option<f64>>],
+) -> Result<(), cairo::Error> {
+    for (line, color) in compared.iter().zip(COMPARISON_COLORS.iter().cycle()) {
+        trace_line(cr, layout, scale, line);
+        set_source_color(cr, *color);
+        cr.stroke()?;
+    }
+
+    Ok(())
+}
+
+/// Adds `line` to the path, breaking it off where it has no value.
+fn trace_line(cr: &Context, layout: &Layout, scale: &PriceScale, line: &[Option<f64>]) {
+    let viewport = layout.viewport;
+    let visible = line[viewport.start..viewport.end].iter();
+
+    for (i, value) in (viewport.start..).zip(visible) {
+        match value {
+            Some(value) => cr.line_to(layout.bar_x(i), scale.y(*value)),
+            None => cr.new_sub_path(),
+        }
+    }
+}
+
+fn draw_selection(
+    cr: &Context,
+    layout: &Layout,
+    (start_x, end_x): (f64, f64),
+) -> Result<(), cairo::Error> {
+    let (red, green, blue, alpha) = SELECTION_COLOR;
+    cr.set_source_rgba(red, green, blue, alpha);
+    cr.rectangle(
+        start_x.min(end_x),
+        0.0,
+        (end_x - start_x).abs(),
+        layout.plot_bottom,
+    );
+
+    cr.fill()
+}
+
+/// Periods that closed lower than they opened are drawn as down.
+fn bar_color(quote: &Quote) -> (f64, f64, f64) {
+    if quote.close < quote.open {
+        DOWN_COLOR
+    } else {
+        UP_COLOR
+    }
+}
+
+fn set_source_color(cr: &Context, (red, green, blue): (f64, f64, f64)) {
+    cr.set_source_rgb(red, green, blue);
+}
+
+/// The date and time `time` shows on the clock, leaving out its time zone.
+fn wall_clock(time: &glib::DateTime) -> Option<NaiveDateTime> {
+    NaiveDate::from_ymd_opt(time.year(), time.month() as u32, time.day_of_month() as u32)?
+        .and_hms_opt(
+            time.hour() as u32,
+            time.minute() as u32,
+            time.second() as u32,
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn viewport(start: usize, end: usize) -> Viewport {
+        Viewport { start, end }
+    }
+
+    #[test]
+    fn viewport_of_fewer_bars_than_shown_at_least() {
+        assert_eq!(Viewport::new(1, 2, 3), viewport(0, 3));
+        assert_eq!(Viewport::new(10, 20, 3), viewport(0, 3));
+    }
+
+    #[test]
+    fn viewport_of_no_bars() {
+        let empty = Viewport::new(0, 10, 0);
+
+        assert_eq!(empty, viewport(0, 0));
+        assert_eq!(empty.zoom(0.5, 0, 0), empty);
+        assert_eq!(empty.pan(3, 0), empty);
+        assert_eq!(empty.pan(-3, 0), empty);
+    }
+
+    #[test]
+    fn viewport_keeps_its_width_at_the_ends() {
+        assert_eq!(Viewport::new(98, 99, 100), viewport(95, 100));
+        assert_eq!(Viewport::new(2, 3, 100), viewport(2, 7));
+    }
+
+    #[test]
+    fn zoom_keeps_the_anchor_in_place() {
+        let full = viewport(0, 100);
+
+        assert_eq!(full.zoom(0.5, 0, 100), viewport(0, 50));
+        assert_eq!(full.zoom(0.5, 100, 100), viewport(50, 100));
+        assert_eq!(full.zoom(0.5, 50, 100), viewport(25, 75));
+        // An anchor out of view is taken to be at the nearest edge
+        assert_eq!(viewport(20, 40).zoom(0.5, 90, 100), viewport(30, 40));
+    }
+
+    #[test]
+    fn zoom_in_stops_at_the_minimum_width() {
+        assert_eq!(
+            viewport(0, 100).zoom(0.001, 0, 100),
+            viewport(0, MIN_VISIBLE_BARS)
+        );
+    }
+
+    #[test]
+    fn zoom_out_stops_at_every_bar() {
+        assert_eq!(viewport(40, 60).zoom(100.0, 50, 100), viewport(0, 100));
+        assert_eq!(viewport(40, 60).zoom(f64::MAX, 60, 100), viewport(0, 100));
+    }
+
+    #[test]
+    fn pan_stops_at_both_ends() {
+        assert_eq!(viewport(40, 60).pan(10, 100), viewport(50, 70));
+        assert_eq!(viewport(40, 60).pan(1000, 100), viewport(80, 100));
+        assert_eq!(viewport(40, 60).pan(-1000, 100), viewport(0, 20));
+    }
+
+    #[test]
+    fn pan_over_fewer_bars_than_in_view() {
+        assert_eq!(viewport(40, 60).pan(5, 10), viewport(0, 10));
+    }
+}
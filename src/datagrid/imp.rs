@@ -1,4 +1,7 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::fs::File;
+use std::path::Path;
+use std::rc::Rc;
 
 use gtk4::glib::subclass::types::ObjectSubclass;
 use gtk4::glib::*;
@@ -7,9 +10,18 @@ use gtk4::subclass::prelude::*;
 use gtk4::*;
 
 use crate::data_helper::*;
+use crate::datagrid::chart::{Chart, ChartStyle, ChartView, ExportFormat, Viewport};
+use crate::dialogs::show_export_failed_dialog;
+use crate::format_helper::{fmt_as_of, fmt_chart_timestamp, fmt_large_number, fmt_number, MISSING};
+use crate::fs_persistence::{read_indicators, write_indicators};
+use crate::history_export::{write_history, HistoryFormat, HistorySessions, HistoryTimeZone};
+use crate::indicators::{Indicator, INDICATORS};
+use crate::LOG_DOMAIN;
 
 use once_cell::sync::Lazy;
 
+use yahoo_finance_api::Quote;
+
 use gettextrs::gettext;
 
 #[derive(Default)]
@@ -18,6 +30,7 @@ pub struct StoxDataGrid {
     pub name_label: RefCell<Label>,
     pub latest_quote_label: RefCell<Label>,
     pub market_change_label: RefCell<Label>,
+    pub as_of_label: RefCell<Label>,
     pub info_label: RefCell<Label>,
     pub save_btn: RefCell<Button>,
     pub unsave_btn: RefCell<Button>,
@@ -32,9 +45,86 @@ pub struct StoxDataGrid {
     pub yield_label: RefCell<Label>,
     pub beta_label: RefCell<Label>,
     pub eps_label: RefCell<Label>,
+    /// Shared by the charts of every range
+    chart_style: Cell<ChartStyle>,
+    pub chart_tabs: RefCell<Vec<Rc<ChartTab>>>,
+    /// The indicators of the symbol shown, drawn on the chart of every range
+    indicators: RefCell<Vec<Indicator>>,
+    indicator_list: RefCell<ListBox>,
+    /// Other symbols drawn on the chart of every range, kept when another
+    /// symbol is shown
+    comparisons: RefCell<Vec<String>>,
+    comparison_list: RefCell<ListBox>,
+    /// The search for symbols to compare with that waits for typing to stop
+    comparison_search_source: RefCell<Option<SourceId>>,
+    /// Kept open until a file is picked to export the chart or its quotes to
+    export_dialog: RefCell<Option<FileChooserNative>>,
+    /// Kept around to hear about changes to the chart settings
+    settings: once_cell::unsync::OnceCell<gio::Settings>,
+    /// Whether last known values or an error are shown because the network
+    /// was down
+    pub outdated: Cell<bool>,
+    /// The pending retry while `outdated`, only ever one at a time
+    pub retry_source: RefCell<Option<SourceId>>,
 }
 
 pub const GRID_WIDTH: i32 = 850;
+
+/// How far the crosshair label keeps from the crosshair and the top edge
+const CROSSHAIR_LABEL_OFFSET: f64 = 12.0;
+
+/// How long typing has to stop for before symbols are searched for, like in
+/// the sidebar
+const SEARCH_DELAY: std::time::Duration = std::time::Duration::from_millis(250);
+
+/// How much a scroll step or key press zooms out, or in when inverted
+const ZOOM_STEP: f64 = 1.2;
+/// How much of the view a sideways scroll step pans
+const PAN_STEP: f64 = 0.1;
+/// Drags narrower than this are taken as clicks rather than a selection
+const MIN_SELECTION_WIDTH: f64 = 5.0;
+
+const MIN_INDICATOR_PERIOD: f64 = 2.0;
+const MAX_INDICATOR_PERIOD: f64 = 500.0;
+
+/// The formats offered to export the chart to, in the order they are listed
+const EXPORT_FORMATS: [ExportFormat; 2] = [ExportFormat::Png, ExportFormat::Svg];
+/// The formats offered to export the quotes to, in the order they are listed
+const HISTORY_FORMATS: [HistoryFormat; 2] = [HistoryFormat::Csv, HistoryFormat::JsonLines];
+const HISTORY_TIME_ZONES: [HistoryTimeZone; 2] = [HistoryTimeZone::Utc, HistoryTimeZone::Exchange];
+const HISTORY_SESSIONS: [HistorySessions; 2] = [HistorySessions::Regular, HistorySessions::All];
+/// The labels of the intervals quotes can be exported at, like those of
+/// `CHART_RANGES`
+const HISTORY_INTERVALS: [(&str, &str); 9] = [
+    ("1m", "1min"),
+    ("2m", "2min"),
+    ("5m", "5min"),
+    ("15m", "15min"),
+    ("30m", "30min"),
+    ("60m", "1H"),
+    ("1d", "1D"),
+    ("1wk", "1W"),
+    ("1mo", "1M"),
+];
+const DEFAULT_EXPORT_WIDTH: f64 = 1200.0;
+const DEFAULT_EXPORT_HEIGHT: f64 = 600.0;
+const MIN_EXPORT_SIZE: f64 = 100.0;
+const MAX_EXPORT_SIZE: f64 = 10000.0;
+
+/// The chart ranges offered as notebook tabs, with their tab labels.
+pub const CHART_RANGES: [(&str, &str); 11] = [
+    ("1d", "1D"),
+    ("5d", "5D"),
+    ("1mo", "1M"),
+    ("3mo", "3M"),
+    ("6mo", "6M"),
+    ("1y", "1Y"),
+    ("2y", "2Y"),
+    ("5y", "5Y"),
+    ("10y", "10Y"),
+    ("ytd", "YTD"),
+    ("max", "MAX"),
+];
 pub const SYMBOL_LABEL_MARGIN_END: i32 = 10;
 
 macro_rules! stat_col {
@@ -146,13 +236,383 @@ impl ObjectImpl for StoxDataGrid {
             .build();
         market_change_label.show();
 
+        let as_of_label = Label::builder()
+            .halign(Align::End)
+            .css_classes(vec!["as_of"])
+            .build();
+        as_of_label.hide();
+
         let notebook = Notebook::builder()
             .focusable(true)
             .hexpand(true)
             .height_request(350)
             .margin_top(15)
+            .scrollable(true)
+            .build();
+
+        let chart_style_box = Box::builder()
+            .css_classes(vec!["linked"])
+            .valign(Align::Center)
+            .margin_end(5)
+            .build();
+
+        let chart_styles = [
+            (ChartStyle::Line, gettext("Line")),
+            (ChartStyle::Area, gettext("Area")),
+            (ChartStyle::Candle, gettext("Candles")),
+        ];
+        let mut group: Option<ToggleButton> = None;
+        for (style, label) in chart_styles {
+            let button = ToggleButton::builder()
+                .label(label)
+                .active(style == self.chart_style.get())
+                .build();
+            button.set_group(group.as_ref());
+            button.connect_toggled(clone!(@weak self as this => move |button| {
+                if button.is_active() {
+                    this.set_chart_style(style);
+                }
+            }));
+
+            chart_style_box.append(&button);
+            group.get_or_insert(button);
+        }
+
+        let reset_zoom_btn = Button::builder()
+            .icon_name("zoom-original-symbolic")
+            .tooltip_text(gettext("Reset Zoom"))
+            .valign(Align::Center)
+            .build();
+        reset_zoom_btn.connect_clicked(clone!(@weak self as this => move |_| {
+            if let Some(tab) = this.current_chart_tab() {
+                tab.reset_zoom();
+            }
+        }));
+
+        // In the order of `INDICATORS`
+        let indicator_kind_dropdown = DropDown::from_strings(&[
+            &gettext("Simple Moving Average"),
+            &gettext("Exponential Moving Average"),
+            &gettext("Bollinger Bands"),
+            &gettext("Volume Weighted Average Price"),
+            &gettext("Relative Strength Index"),
+            &gettext("MACD"),
+            &gettext("Stochastic Oscillator"),
+        ]);
+
+        let indicator_period_spin =
+            SpinButton::with_range(MIN_INDICATOR_PERIOD, MAX_INDICATOR_PERIOD, 1.0);
+        indicator_period_spin.set_tooltip_text(Some(&gettext("Period")));
+
+        let show_default_period = clone!(@weak indicator_period_spin => move |dropdown: &DropDown| {
+            let period = INDICATORS[dropdown.selected() as usize].period();
+
+            indicator_period_spin.set_sensitive(period.is_some());
+            if let Some(period) = period {
+                indicator_period_spin.set_value(period as f64);
+            }
+        });
+        show_default_period(&indicator_kind_dropdown);
+        indicator_kind_dropdown.connect_selected_notify(show_default_period);
+
+        let add_indicator_btn = Button::with_label(&gettext("Add"));
+        add_indicator_btn.connect_clicked(clone!(
+            @weak self as this, @weak indicator_kind_dropdown, @weak indicator_period_spin =>
+            move |_| {
+                let indicator = INDICATORS[indicator_kind_dropdown.selected() as usize]
+                    .with_period(indicator_period_spin.value_as_int() as usize);
+
+                let mut indicators = this.indicators.borrow().clone();
+                if !indicators.contains(&indicator) {
+                    indicators.push(indicator);
+                    this.set_indicators(indicators);
+                }
+            }
+        ));
+
+        let add_indicator_box = Box::new(Orientation::Horizontal, 6);
+        add_indicator_box.append(&indicator_kind_dropdown);
+        add_indicator_box.append(&indicator_period_spin);
+        add_indicator_box.append(&add_indicator_btn);
+
+        let indicator_list = ListBox::builder()
+            .selection_mode(SelectionMode::None)
+            .visible(false)
+            .build();
+
+        let indicators_box = Box::new(Orientation::Vertical, 6);
+        indicators_box.append(&indicator_list);
+        indicators_box.append(&add_indicator_box);
+
+        *self.indicator_list.borrow_mut() = indicator_list;
+
+        let indicators_btn = MenuButton::builder()
+            .label(gettext("Indicators"))
+            .popover(&Popover::builder().child(&indicators_box).build())
+            .valign(Align::Center)
+            .build();
+
+        let comparison_list = ListBox::builder()
+            .selection_mode(SelectionMode::None)
+            .visible(false)
+            .build();
+
+        let comparison_search_entry = SearchEntry::builder()
+            .placeholder_text(gettext("Search for a symbol..."))
+            .build();
+
+        let comparison_search_results = ListBox::builder()
+            .selection_mode(SelectionMode::None)
+            .activate_on_single_click(true)
+            .build();
+        comparison_search_results.connect_row_activated(clone!(
+            @weak self as this, @weak comparison_search_entry => move |_, row| {
+                let symbol = row.widget_name().to_string();
+
+                let mut comparisons = this.comparisons.borrow().clone();
+                if !comparisons.contains(&symbol) {
+                    comparisons.push(symbol.clone());
+                    this.set_comparisons(comparisons);
+
+                    for tab in this.chart_tabs.borrow().iter() {
+                        this.load_comparison(tab, symbol.clone());
+                    }
+                }
+
+                comparison_search_entry.set_text("");
+            }
+        ));
+
+        comparison_search_entry.connect_search_changed(clone!(
+            @weak self as this, @weak comparison_search_results => move |entry| {
+                while let Some(row) = comparison_search_results.first_child() {
+                    comparison_search_results.remove(&row);
+                }
+
+                if let Some(source) = this.comparison_search_source.take() {
+                    source.remove();
+                }
+
+                // Do not try to ping Yahoo with invalid characters.
+                let query = entry.text().to_string();
+                if query.is_empty() || !query.is_ascii() {
+                    return;
+                }
+
+                let source = timeout_add_local_once(
+                    SEARCH_DELAY,
+                    clone!(@weak this, @weak entry, @weak comparison_search_results => move || {
+                        // The source is gone once it has run
+                        this.comparison_search_source.take();
+                        search_comparisons(&entry, &comparison_search_results, query);
+                    }),
+                );
+                this.comparison_search_source.replace(Some(source));
+            }
+        ));
+
+        let comparisons_box = Box::new(Orientation::Vertical, 6);
+        comparisons_box.append(&comparison_list);
+        comparisons_box.append(&comparison_search_entry);
+        comparisons_box.append(&comparison_search_results);
+
+        *self.comparison_list.borrow_mut() = comparison_list;
+
+        let compare_btn = MenuButton::builder()
+            .label(gettext("Compare with..."))
+            .popover(&Popover::builder().child(&comparisons_box).build())
+            .valign(Align::Center)
             .build();
 
+        let log_scale_btn = ToggleButton::builder()
+            .label(gettext("Log"))
+            .tooltip_text(gettext("Logarithmic Scale"))
+            .valign(Align::Center)
+            .build();
+
+        let local_time_btn = ToggleButton::builder()
+            .icon_name("preferences-system-time-symbolic")
+            .tooltip_text(gettext("Local Time"))
+            .valign(Align::Center)
+            .build();
+
+        // In the order of `EXPORT_FORMATS`
+        let export_format_dropdown = DropDown::from_strings(&["PNG", "SVG"]);
+
+        let export_width_spin = SpinButton::with_range(MIN_EXPORT_SIZE, MAX_EXPORT_SIZE, 1.0);
+        export_width_spin.set_value(DEFAULT_EXPORT_WIDTH);
+        export_width_spin.set_tooltip_text(Some(&gettext("Width")));
+
+        let export_height_spin = SpinButton::with_range(MIN_EXPORT_SIZE, MAX_EXPORT_SIZE, 1.0);
+        export_height_spin.set_value(DEFAULT_EXPORT_HEIGHT);
+        export_height_spin.set_tooltip_text(Some(&gettext("Height")));
+
+        let export_chart_btn = Button::with_label(&gettext("Export..."));
+        export_chart_btn.connect_clicked(clone!(
+            @weak self as this, @weak export_format_dropdown, @weak export_width_spin,
+            @weak export_height_spin => move |_| {
+                this.export_chart(
+                    EXPORT_FORMATS[export_format_dropdown.selected() as usize],
+                    export_width_spin.value_as_int(),
+                    export_height_spin.value_as_int(),
+                );
+            }
+        ));
+
+        let export_chart_box = Box::new(Orientation::Horizontal, 6);
+        export_chart_box.append(&export_format_dropdown);
+        export_chart_box.append(&export_width_spin);
+        export_chart_box.append(&Label::new(Some("\u{d7}")));
+        export_chart_box.append(&export_height_spin);
+
+        // In the order of `CHART_RANGES`
+        let history_range_dropdown = DropDown::from_strings(&CHART_RANGES.map(|(_, label)| label));
+        history_range_dropdown.set_tooltip_text(Some(&gettext("Range")));
+
+        let history_interval_dropdown = DropDown::builder()
+            .tooltip_text(gettext("Interval"))
+            .build();
+        set_history_intervals(
+            &history_interval_dropdown,
+            history_range(&history_range_dropdown),
+        );
+        history_range_dropdown.connect_selected_notify(
+            clone!(@weak history_interval_dropdown => move |dropdown| {
+                set_history_intervals(&history_interval_dropdown, history_range(dropdown));
+            }),
+        );
+
+        // In the order of `HISTORY_FORMATS`, `HISTORY_TIME_ZONES` and
+        // `HISTORY_SESSIONS`
+        let history_format_dropdown = DropDown::from_strings(&["CSV", "JSON Lines"]);
+        let history_time_zone_dropdown =
+            DropDown::from_strings(&[&gettext("UTC"), &gettext("Exchange Time")]);
+        history_time_zone_dropdown.set_tooltip_text(Some(&gettext("Time Zone")));
+        let history_sessions_dropdown =
+            DropDown::from_strings(&[&gettext("Regular Hours"), &gettext("All Sessions")]);
+        history_sessions_dropdown.set_tooltip_text(Some(&gettext("Trading Sessions")));
+
+        let export_quotes_btn = Button::with_label(&gettext("Export..."));
+        export_quotes_btn.connect_clicked(clone!(
+            @weak self as this, @weak history_range_dropdown, @weak history_interval_dropdown,
+            @weak history_format_dropdown, @weak history_time_zone_dropdown,
+            @weak history_sessions_dropdown => move |_| {
+                let range = history_range(&history_range_dropdown);
+                let Some(interval) = stox_get_chart_intervals(range)
+                    .get(history_interval_dropdown.selected() as usize)
+                else {
+                    return;
+                };
+
+                this.export_quotes(
+                    range,
+                    interval,
+                    HISTORY_FORMATS[history_format_dropdown.selected() as usize],
+                    HISTORY_TIME_ZONES[history_time_zone_dropdown.selected() as usize],
+                    HISTORY_SESSIONS[history_sessions_dropdown.selected() as usize],
+                );
+            }
+        ));
+
+        let export_quotes_box = Box::new(Orientation::Horizontal, 6);
+        export_quotes_box.append(&history_range_dropdown);
+        export_quotes_box.append(&history_interval_dropdown);
+        export_quotes_box.append(&history_format_dropdown);
+        export_quotes_box.append(&history_time_zone_dropdown);
+        export_quotes_box.append(&history_sessions_dropdown);
+
+        let export_grid = Grid::builder().row_spacing(6).column_spacing(6).build();
+        export_grid.attach(
+            &Label::builder()
+                .label(gettext("Chart"))
+                .halign(Align::Start)
+                .build(),
+            0,
+            0,
+            1,
+            1,
+        );
+        export_grid.attach(&export_chart_box, 1, 0, 1, 1);
+        export_grid.attach(&export_chart_btn, 2, 0, 1, 1);
+        export_grid.attach(
+            &Label::builder()
+                .label(gettext("Quotes"))
+                .halign(Align::Start)
+                .build(),
+            0,
+            1,
+            1,
+            1,
+        );
+        export_grid.attach(&export_quotes_box, 1, 1, 1, 1);
+        export_grid.attach(&export_quotes_btn, 2, 1, 1, 1);
+
+        let export_popover = Popover::builder().child(&export_grid).build();
+        // The quotes default to the range shown
+        export_popover.connect_show(clone!(
+            @weak self as this, @weak history_range_dropdown => move |_| {
+                let range = this.current_chart_tab().map(|tab| tab.range);
+                if let Some(position) = CHART_RANGES
+                    .iter()
+                    .position(|(chart_range, _)| Some(*chart_range) == range)
+                {
+                    history_range_dropdown.set_selected(position as u32);
+                }
+            }
+        ));
+
+        let export_btn = MenuButton::builder()
+            .icon_name("document-save-as-symbolic")
+            .tooltip_text(gettext("Export"))
+            .popover(&export_popover)
+            .valign(Align::Center)
+            .build();
+
+        let chart_actions = Box::new(Orientation::Horizontal, 6);
+        chart_actions.append(&export_btn);
+        chart_actions.append(&compare_btn);
+        chart_actions.append(&indicators_btn);
+        chart_actions.append(&log_scale_btn);
+        chart_actions.append(&local_time_btn);
+        chart_actions.append(&reset_zoom_btn);
+        chart_actions.append(&chart_style_box);
+
+        notebook.set_action_widget(&chart_actions, PackType::End);
+
+        // Each range is only fetched the first time its tab is shown
+        notebook.connect_switch_page(clone!(@weak self as this => move |_, page, _| {
+            this.load_chart(page);
+        }));
+
+        let settings = gio::Settings::new(crate::APP_ID);
+        settings.connect_changed(
+            Some("chart-local-time"),
+            clone!(@weak self as this => move |settings, key| {
+                let local_time = settings.boolean(key);
+
+                for tab in this.chart_tabs.borrow().iter() {
+                    tab.update_view(|view| view.local_time = local_time);
+                }
+            }),
+        );
+        settings.connect_changed(
+            Some("chart-log-scale"),
+            clone!(@weak self as this => move |settings, key| {
+                let log_scale = settings.boolean(key);
+
+                for tab in this.chart_tabs.borrow().iter() {
+                    tab.update_view(|view| view.log_scale = log_scale);
+                }
+            }),
+        );
+        settings
+            .bind("chart-local-time", &local_time_btn, "active")
+            .build();
+        settings
+            .bind("chart-log-scale", &log_scale_btn, "active")
+            .build();
+        let _ = self.settings.set(settings);
+
         grid.attach(&symbol_label, 0, 0, 1, 1);
         grid.attach(&name_label, 1, 0, 1, 1);
         grid.attach(&info_label, 0, 1, 3, 1);
@@ -166,6 +626,7 @@ impl ObjectImpl for StoxDataGrid {
             .build();
         quote_box.append(&latest_quote_label);
         quote_box.append(&market_change_label);
+        quote_box.append(&as_of_label);
 
         grid.attach(&quote_box, 2, 0, 1, 1);
         grid.attach(&notebook, 0, 2, 3, 2);
@@ -274,6 +735,7 @@ impl ObjectImpl for StoxDataGrid {
         *self.name_label.borrow_mut() = name_label;
         *self.latest_quote_label.borrow_mut() = latest_quote_label;
         *self.market_change_label.borrow_mut() = market_change_label;
+        *self.as_of_label.borrow_mut() = as_of_label;
         *self.info_label.borrow_mut() = info_label;
         *self.notebook.borrow_mut() = notebook;
     }
@@ -284,110 +746,682 @@ impl BoxImpl for StoxDataGrid {}
 impl WidgetImpl for StoxDataGrid {}
 
 impl StoxDataGrid {
-    pub fn construct_graph(
-        &self,
-        main_info: MainInfo,
-        extended_info: ExtendedInfo,
-        mut quotes: Vec<f64>,
-    ) {
-        let x_axis = stox_get_chart_x_axis(&main_info, "1d");
-        if x_axis.is_err() {
-            return;
+    /// Appends a tab per chart range. The tabs stay empty until shown.
+    pub fn construct_chart_tabs(&self) {
+        let notebook = self.notebook.borrow().clone();
+
+        let symbol = self.symbol_label.borrow().label();
+        self.indicators
+            .replace(read_indicators(&symbol).unwrap_or_default());
+        self.update_indicators();
+
+        for (_, label) in CHART_RANGES {
+            let page = Box::new(Orientation::Vertical, 0);
+            notebook.append_page(&page, Some(&Label::new(Some(label))));
         }
-        let x_axis = x_axis.unwrap();
 
-        let y_axis = stox_get_chart_y_axis(&extended_info);
-        if y_axis.is_err() {
-            return;
+        // The first tab was selected while it was being added, before it
+        // could be told apart from the others
+        if let Some(page) = notebook.current_page() {
+            if let Some(page) = notebook.nth_page(Some(page)) {
+                self.load_chart(&page);
+            }
         }
-        let y_axis = y_axis.unwrap();
+    }
 
-        let drawing_area = DrawingArea::new();
+    fn load_chart(&self, page: &Widget) {
+        let notebook = self.notebook.borrow().clone();
 
-        self.notebook
-            .borrow_mut()
-            .append_page(&drawing_area, Some(&Label::new(Some("1D"))));
+        let Some(page) = page.downcast_ref::<Box>().cloned() else {
+            return;
+        };
+        let Some((range, _)) = notebook
+            .page_num(&page)
+            .and_then(|page_num| CHART_RANGES.get(page_num as usize))
+        else {
+            return;
+        };
 
-        drawing_area.set_draw_func(move |_drawing_area, cr, width, height| {
-            let mut x_iter = x_axis.iter();
-            let mut y_iter = y_axis.iter();
+        if page.first_child().is_some() {
+            return; // loading or loaded already
+        }
 
-            // Background color
-            #[allow(clippy::eq_op)]
-            cr.set_source_rgb(56.0 / 255.0, 56.0 / 255.0, 56.0 / 255.0);
-            cr.paint().unwrap();
-            cr.set_line_width(1.0);
+        let spinner = Spinner::builder()
+            .spinning(true)
+            .halign(Align::Center)
+            .valign(Align::Center)
+            .vexpand(true)
+            .build();
+        page.append(&spinner);
 
-            cr.set_source_rgb(0.0, 255.0, 0.0);
-            if extended_info.market_change_neg() {
-                cr.set_source_rgb(255.0, 0.0, 0.0);
-            }
+        let symbol = self.symbol_label.borrow().label().to_string();
 
-            let mut lines_step = quotes.len();
-            let new_quotes = stox_scale_quotes(&mut quotes, height);
-            let mut quote_iter = new_quotes.iter().rev(); // reverse or else it is reflected
+        let (sender, receiver) = MainContext::channel(PRIORITY_DEFAULT);
 
-            // Don't panic on a lot of quotes
-            if lines_step > width as usize {
-                lines_step = width as usize;
-            }
+        std::thread::spawn(move || sender.send(stox_get_quotes(symbol, range)).unwrap());
 
-            cr.move_to(0.0, *quote_iter.next().unwrap()); // start at the first point
+        receiver.attach(
+            None,
+            clone!(@weak self as this => @default-return Continue(false), move |chart| {
+                // The tab is gone if another symbol was shown in the meantime
+                if notebook.page_num(&page).is_none() {
+                    return Continue(false);
+                }
 
-            // Draw the actual chart and change in quote
-            for i in (0..=width).step_by(width as usize / lines_step) {
-                let next = quote_iter.next();
+                page.remove(&spinner);
 
-                // if we hit None, we are done
-                if let Some(next) = next {
-                    cr.line_to(i as f64, *next);
-                    cr.line_to(i as f64, height as f64 - 4.0);
-                    cr.stroke().unwrap();
-                    cr.line_to(i as f64, *next);
+                match chart {
+                    Ok(chart) => page.append(&this.construct_graph(&page, range, chart)),
+                    Err(_) => page.append(&Label::builder()
+                        .label(gettext("The graph could not be loaded."))
+                        .vexpand(true)
+                        .build()),
                 }
-            }
 
-            // Set the grid lines color
-            #[allow(clippy::eq_op)]
-            cr.set_source_rgb(255.0 / 255.0, 255.0 / 255.0, 255.0 / 255.0);
+                Continue(false)
+            }),
+        );
+    }
+
+    fn construct_graph(&self, page: &Box, range: &'static str, chart: ChartInfo) -> Overlay {
+        let drawing_area = DrawingArea::builder().vexpand(true).focusable(true).build();
+        let settings = self.settings.get();
+
+        let symbol = self.symbol_label.borrow().label();
+        let as_of = chart.as_of;
+        let tab = Rc::new(ChartTab {
+            page: page.downgrade(),
+            drawing_area: drawing_area.downgrade(),
+            range,
+            chart: Chart::new(&symbol, chart),
+            view: Cell::new(ChartView {
+                style: self.chart_style.get(),
+                local_time: settings.is_some_and(|settings| settings.boolean("chart-local-time")),
+                log_scale: settings.is_some_and(|settings| settings.boolean("chart-log-scale")),
+                ..Default::default()
+            }),
+            drag: Cell::new(None),
+        });
+        tab.chart.set_indicators(&self.indicators.borrow());
+        self.chart_tabs.borrow_mut().push(tab.clone());
 
-            // x-axis (horizontal) lines
-            for x_grid_line in (0..width).step_by(width as usize / 8) {
-                cr.move_to(x_grid_line as f64, height as f64 - 5.0);
-                cr.line_to(x_grid_line as f64, -height as f64);
-                cr.stroke().unwrap();
+        for symbol in self.comparisons.borrow().iter() {
+            self.load_comparison(&tab, symbol.clone());
+        }
 
-                cr.move_to(x_grid_line as f64 + 2.0, height as f64 - 5.0);
+        let crosshair_label = Label::builder()
+            .css_classes(vec!["chart_crosshair_label"])
+            .halign(Align::Start)
+            .valign(Align::Start)
+            .can_target(false)
+            .build();
+        crosshair_label.hide();
+
+        let overlay = Overlay::builder().child(&drawing_area).build();
+        overlay.add_overlay(&crosshair_label);
+
+        // Bars served from the cache while offline are marked like the
+        // values above the chart
+        if let Some(as_of) = as_of {
+            let as_of_label = Label::builder()
+                .label(fmt_as_of(as_of))
+                .css_classes(vec!["as_of"])
+                .halign(Align::End)
+                .valign(Align::Start)
+                .can_target(false)
+                .build();
+            overlay.add_overlay(&as_of_label);
+        }
 
-                // Some text can crash on Option Instruments, catch any potential bad unwraps
-                let text = x_iter.next();
-                if let Some(text) = text {
-                    cr.show_text(text).unwrap();
+        drawing_area.set_draw_func(clone!(
+            @strong tab => move |_drawing_area, cr, width, height| {
+                if let Err(err) = tab.chart.draw(cr, width, height, &tab.view.get()) {
+                    glib::g_warning!(LOG_DOMAIN, "Could not draw the chart: {}", err);
                 }
             }
+        ));
+
+        let move_crosshair = clone!(
+            @weak drawing_area, @weak crosshair_label, @strong tab =>
+            move |index: Option<usize>| {
+                tab.update_view(|view| view.crosshair = index);
 
-            // y-axis (vertical) lines
-            let mut first_yaxis_item_hidden = false;
-            for y_grid_line in (0..height).step_by(height as usize / 4).rev() {
-                cr.move_to(0.0, y_grid_line as f64);
-                cr.line_to(width as f64, y_grid_line as f64);
-                cr.stroke().unwrap();
-
-                cr.move_to(2.0, y_grid_line as f64);
-
-                // Don't have the axis text overlap
-                if first_yaxis_item_hidden {
-                    // Some text can crash on Option Instruments, catch any potential bad unwraps
-                    let text = y_iter.next();
-                    if let Some(text) = text {
-                        cr.show_text(&format!("{:.2}", text)).unwrap();
+                let Some((index, quote)) =
+                    index.and_then(|index| Some((index, tab.chart.quote(index)?)))
+                else {
+                    crosshair_label.hide();
+                    return;
+                };
+
+                let time = tab.chart.time(index, &tab.view.get());
+                crosshair_label.set_label(&fmt_crosshair_label(quote, time));
+                crosshair_label.show();
+
+                // Keep the label next to the crosshair, on whichever side has room
+                let width = drawing_area.width();
+                let x = tab.chart.bar_x(index, width, &tab.view.get());
+                let label_width = crosshair_label.preferred_size().1.width() as f64;
+                let margin = if x + CROSSHAIR_LABEL_OFFSET + label_width > width as f64 {
+                    x - CROSSHAIR_LABEL_OFFSET - label_width
+                } else {
+                    x + CROSSHAIR_LABEL_OFFSET
+                };
+                crosshair_label.set_margin_start(margin.max(0.0) as i32);
+                crosshair_label.set_margin_top(CROSSHAIR_LABEL_OFFSET as i32);
+            }
+        );
+
+        let motion = EventControllerMotion::new();
+        motion.connect_motion(clone!(
+            @weak drawing_area, @strong tab, @strong move_crosshair => move |_, x, _| {
+                let index = tab.chart.bar_at(x, drawing_area.width(), &tab.view.get());
+                move_crosshair(Some(index));
+            }
+        ));
+        motion.connect_leave(clone!(@strong move_crosshair => move |_| move_crosshair(None)));
+        drawing_area.add_controller(motion);
+
+        // Dragging selects a window to zoom into, dragging with any other
+        // button or with Shift held pans instead
+        let drag = GestureDrag::builder().button(0).build();
+        drag.connect_drag_begin(clone!(
+            @weak drawing_area, @strong tab => move |gesture, x, _| {
+                drawing_area.grab_focus();
+
+                let pan = gesture.current_button() != gdk::BUTTON_PRIMARY
+                    || gesture
+                        .current_event_state()
+                        .contains(gdk::ModifierType::SHIFT_MASK);
+                tab.drag.set(Some(if pan {
+                    ChartDrag::Pan {
+                        viewport: tab.chart.visible(&tab.view.get()),
                     }
                 } else {
-                    first_yaxis_item_hidden = true;
+                    ChartDrag::Select { start_x: x }
+                }));
+            }
+        ));
+        drag.connect_drag_update(clone!(
+            @weak drawing_area, @strong tab => move |_, offset_x, _| {
+                match tab.drag.get() {
+                    Some(ChartDrag::Select { start_x }) => {
+                        tab.update_view(|view| view.selection = Some((start_x, start_x + offset_x)));
+                    }
+                    Some(ChartDrag::Pan { viewport }) => {
+                        let bars_per_pixel =
+                            tab.chart.bars_per_pixel(drawing_area.width(), &tab.view.get());
+                        let bars = (-offset_x * bars_per_pixel).round() as isize;
+                        tab.zoom_to(viewport.pan(bars, tab.chart.bar_count()));
+                    }
+                    None => {}
                 }
             }
-        });
+        ));
+        drag.connect_drag_end(clone!(
+            @weak drawing_area, @strong tab => move |_, offset_x, _| {
+                if let Some(ChartDrag::Select { start_x }) = tab.drag.take() {
+                    tab.update_view(|view| view.selection = None);
+
+                    if offset_x.abs() >= MIN_SELECTION_WIDTH {
+                        let width = drawing_area.width();
+                        let view = tab.view.get();
+                        let start = tab.chart.bar_at(start_x, width, &view);
+                        let end = tab.chart.bar_at(start_x + offset_x, width, &view);
+
+                        tab.zoom_to(Viewport::new(
+                            start.min(end),
+                            start.max(end) + 1,
+                            tab.chart.bar_count(),
+                        ));
+                    }
+                }
+            }
+        ));
+        drawing_area.add_controller(drag);
+
+        // Scrolling zooms around the crosshair, scrolling sideways pans
+        let scroll = EventControllerScroll::new(EventControllerScrollFlags::BOTH_AXES);
+        scroll.connect_scroll(clone!(@strong tab => move |_, delta_x, delta_y| {
+            let view = tab.view.get();
+            let viewport = tab.chart.visible(&view);
+            let anchor = view.crosshair.unwrap_or((viewport.start + viewport.end) / 2);
+            let count = tab.chart.bar_count();
+
+            let viewport = viewport.zoom(ZOOM_STEP.powf(delta_y), anchor, count);
+            let bars = (delta_x * viewport.len() as f64 * PAN_STEP).round() as isize;
+            tab.zoom_to(viewport.pan(bars, count));
+
+            Inhibit(true)
+        }));
+        drawing_area.add_controller(scroll);
+
+        let keys = EventControllerKey::new();
+        keys.connect_key_pressed(clone!(
+            @strong tab, @strong move_crosshair =>
+            @default-return Inhibit(false),
+            move |_, key, _, _| {
+                let view = tab.view.get();
+                let viewport = tab.chart.visible(&view);
+                let count = tab.chart.bar_count();
+                let anchor = view.crosshair.unwrap_or((viewport.start + viewport.end) / 2);
+
+                // There is nothing to move between
+                if count == 0 {
+                    return Inhibit(false);
+                }
+
+                let index = match (key, view.crosshair) {
+                    (gdk::Key::Left, Some(index)) => index.saturating_sub(1),
+                    (gdk::Key::Right, Some(index)) => (index + 1).min(count - 1),
+                    (gdk::Key::Left | gdk::Key::Right | gdk::Key::End, _) => viewport.end - 1,
+                    (gdk::Key::Home, _) => viewport.start,
+                    (gdk::Key::plus | gdk::Key::equal | gdk::Key::KP_Add, _) => {
+                        tab.zoom_to(viewport.zoom(1.0 / ZOOM_STEP, anchor, count));
+                        return Inhibit(true);
+                    }
+                    (gdk::Key::minus | gdk::Key::KP_Subtract, _) => {
+                        tab.zoom_to(viewport.zoom(ZOOM_STEP, anchor, count));
+                        return Inhibit(true);
+                    }
+                    (gdk::Key::_0 | gdk::Key::KP_0, _) => {
+                        tab.reset_zoom();
+                        return Inhibit(true);
+                    }
+                    (gdk::Key::Escape, Some(_)) => {
+                        move_crosshair(None);
+                        return Inhibit(true);
+                    }
+                    _ => return Inhibit(false),
+                };
+
+                // Scroll along when the crosshair leaves the view
+                if index < viewport.start {
+                    tab.zoom_to(viewport.pan(index as isize - viewport.start as isize, count));
+                } else if index >= viewport.end {
+                    tab.zoom_to(viewport.pan(index as isize - viewport.end as isize + 1, count));
+                }
+
+                move_crosshair(Some(index));
+                Inhibit(true)
+            }
+        ));
+        drawing_area.add_controller(keys);
+
+        overlay.show();
+
+        overlay
+    }
+
+    /// Draws the chart shown as it is seen, but at `width` by `height`.
+    fn export_chart(&self, format: ExportFormat, width: i32, height: i32) {
+        let Some(tab) = self.current_chart_tab() else {
+            return; // still loading
+        };
+
+        self.export_to_file(
+            &gettext("Export Chart"),
+            &format!("{}-{}", tab.chart.symbol(), tab.range),
+            format.extension(),
+            move |this, path| {
+                let exported = tab
+                    .chart
+                    .export(path, format, width, height, &tab.view.get());
+
+                if let Err(err) = exported {
+                    this.show_export_failed(&err.to_string());
+                }
+            },
+        );
+    }
+
+    /// Fetches the bars of the symbol shown at `interval` and writes them out.
+    fn export_quotes(
+        &self,
+        range: &'static str,
+        interval: &'static str,
+        format: HistoryFormat,
+        time_zone: HistoryTimeZone,
+        sessions: HistorySessions,
+    ) {
+        let symbol = self.symbol_label.borrow().label().to_string();
+
+        self.export_to_file(
+            &gettext("Export Quotes"),
+            &format!("{}-{}-{}", symbol, range, interval),
+            format.extension(),
+            move |this, path| {
+                let (sender, receiver) = MainContext::channel(PRIORITY_DEFAULT);
+
+                std::thread::spawn(clone!(@strong symbol, @to-owned path => move || {
+                    let written = stox_get_quote_history(&symbol, range, interval)
+                        .map_err(|err| err.message())
+                        .and_then(|info| {
+                            File::create(path)
+                                .and_then(|mut file| {
+                                    write_history(&mut file, &info, format, time_zone, sessions)
+                                })
+                                .map_err(|err| err.to_string())
+                        });
+
+                    let _ = sender.send(written);
+                }));
+
+                receiver.attach(
+                    None,
+                    clone!(@weak this => @default-return Continue(false), move |written| {
+                        if let Err(reason) = written {
+                            this.show_export_failed(&reason);
+                        }
+
+                        Continue(false)
+                    }),
+                );
+            },
+        );
+    }
 
-        drawing_area.show();
+    /// Asks where to save a file, suggesting `name`, then has `write` write
+    /// it there.
+    fn export_to_file(
+        &self,
+        title: &str,
+        name: &str,
+        extension: &str,
+        write: impl Fn(&Self, &Path) + 'static,
+    ) {
+        let window = self.obj().root().and_downcast::<ApplicationWindow>();
+
+        let filter = FileFilter::new();
+        filter.set_name(Some(&extension.to_uppercase()));
+        filter.add_pattern(&format!("*.{}", extension));
+
+        let dialog = FileChooserNative::builder()
+            .title(title)
+            .action(FileChooserAction::Save)
+            .modal(true)
+            .filter(&filter)
+            .build();
+        dialog.set_transient_for(window.as_ref());
+        dialog.set_current_name(&format!("{}.{}", name, extension));
+
+        dialog.connect_response(clone!(@weak self as this => move |dialog, response| {
+            let path = dialog.file().and_then(|file| file.path());
+
+            if let (ResponseType::Accept, Some(path)) = (response, path) {
+                write(&this, &path);
+            }
+
+            dialog.destroy();
+            this.export_dialog.replace(None);
+        }));
+
+        dialog.show();
+        self.export_dialog.replace(Some(dialog));
+    }
+
+    fn show_export_failed(&self, reason: &str) {
+        if let Some(window) = self.obj().root().and_downcast::<ApplicationWindow>() {
+            show_export_failed_dialog(&window, reason);
+        }
     }
+
+    fn current_chart_tab(&self) -> Option<Rc<ChartTab>> {
+        let notebook = self.notebook.borrow();
+        let page = notebook.nth_page(notebook.current_page())?;
+
+        self.chart_tabs
+            .borrow()
+            .iter()
+            .find(|tab| tab.page.upgrade().is_some_and(|tab_page| tab_page == page))
+            .cloned()
+    }
+
+    /// Draws `indicators` on every chart and saves them for the symbol.
+    fn set_indicators(&self, indicators: Vec<Indicator>) {
+        let symbol = self.symbol_label.borrow().label();
+        write_indicators(&symbol, &indicators).ok();
+
+        self.indicators.replace(indicators);
+        self.update_indicators();
+    }
+
+    /// Brings the indicator list and the charts in line with `indicators`.
+    fn update_indicators(&self) {
+        let indicators = self.indicators.borrow();
+
+        let indicator_list = self.indicator_list.borrow().clone();
+        while let Some(row) = indicator_list.first_child() {
+            indicator_list.remove(&row);
+        }
+
+        for (index, indicator) in indicators.iter().enumerate() {
+            let name_label = Label::builder()
+                .label(indicator.name())
+                .halign(Align::Start)
+                .hexpand(true)
+                .build();
+
+            let remove_btn = Button::builder()
+                .icon_name("list-remove-symbolic")
+                .tooltip_text(gettext("Remove"))
+                .build();
+            remove_btn.connect_clicked(clone!(@weak self as this => move |_| {
+                let mut indicators = this.indicators.borrow().clone();
+                indicators.remove(index);
+                this.set_indicators(indicators);
+            }));
+
+            let row = Box::new(Orientation::Horizontal, 6);
+            row.append(&name_label);
+            row.append(&remove_btn);
+            indicator_list.append(&row);
+        }
+        indicator_list.set_visible(!indicators.is_empty());
+
+        for tab in self.chart_tabs.borrow().iter() {
+            tab.chart.set_indicators(&indicators);
+            tab.update_view(|_| ());
+        }
+    }
+
+    /// Fetches the range of `tab` for `symbol` and draws it on the chart.
+    fn load_comparison(&self, tab: &Rc<ChartTab>, symbol: String) {
+        if symbol == self.symbol_label.borrow().label() {
+            return;
+        }
+
+        let (sender, receiver) = MainContext::channel(PRIORITY_DEFAULT);
+
+        let range = tab.range;
+        std::thread::spawn(clone!(@strong symbol => move || {
+            sender.send(stox_get_quotes(symbol, range)).unwrap()
+        }));
+
+        receiver.attach(
+            None,
+            clone!(@weak self as this, @strong tab => @default-return Continue(false), move |chart| {
+                // Nothing is drawn for symbols without a chart for the range
+                if let Ok(chart) = chart {
+                    tab.chart.add_comparison(&symbol, &chart);
+                    // The symbol could have been removed in the meantime
+                    tab.chart.retain_comparisons(&this.comparisons.borrow());
+                    tab.update_view(|_| ());
+                }
+
+                Continue(false)
+            }),
+        );
+    }
+
+    /// Brings the comparison list and the charts in line with `comparisons`,
+    /// which still need to be loaded if added.
+    fn set_comparisons(&self, comparisons: Vec<String>) {
+        let comparison_list = self.comparison_list.borrow().clone();
+        while let Some(row) = comparison_list.first_child() {
+            comparison_list.remove(&row);
+        }
+
+        for (index, symbol) in comparisons.iter().enumerate() {
+            let symbol_label = Label::builder()
+                .label(symbol)
+                .halign(Align::Start)
+                .hexpand(true)
+                .build();
+
+            let remove_btn = Button::builder()
+                .icon_name("list-remove-symbolic")
+                .tooltip_text(gettext("Remove"))
+                .build();
+            remove_btn.connect_clicked(clone!(@weak self as this => move |_| {
+                let mut comparisons = this.comparisons.borrow().clone();
+                comparisons.remove(index);
+                this.set_comparisons(comparisons);
+            }));
+
+            let row = Box::new(Orientation::Horizontal, 6);
+            row.append(&symbol_label);
+            row.append(&remove_btn);
+            comparison_list.append(&row);
+        }
+        comparison_list.set_visible(!comparisons.is_empty());
+
+        for tab in self.chart_tabs.borrow().iter() {
+            tab.chart.retain_comparisons(&comparisons);
+            tab.update_view(|_| ());
+        }
+
+        self.comparisons.replace(comparisons);
+    }
+
+    fn set_chart_style(&self, style: ChartStyle) {
+        self.chart_style.set(style);
+
+        for tab in self.chart_tabs.borrow().iter() {
+            tab.update_view(|view| view.style = style);
+        }
+    }
+}
+
+/// A loaded chart and how it is being looked at.
+pub struct ChartTab {
+    page: WeakRef<Box>,
+    drawing_area: WeakRef<DrawingArea>,
+    range: &'static str,
+    chart: Chart,
+    view: Cell<ChartView>,
+    drag: Cell<Option<ChartDrag>>,
+}
+
+#[derive(Clone, Copy)]
+enum ChartDrag {
+    Select {
+        start_x: f64,
+    },
+    /// Remembers where the pan started from
+    Pan {
+        viewport: Viewport,
+    },
+}
+
+impl ChartTab {
+    fn update_view(&self, update: impl FnOnce(&mut ChartView)) {
+        let mut view = self.view.get();
+        update(&mut view);
+        self.view.set(view);
+
+        if let Some(drawing_area) = self.drawing_area.upgrade() {
+            drawing_area.queue_draw();
+        }
+    }
+
+    /// Zooming all the way out is the same as not being zoomed in.
+    fn zoom_to(&self, viewport: Viewport) {
+        let viewport = (viewport.len() < self.chart.bar_count()).then_some(viewport);
+        self.update_view(|view| view.viewport = viewport);
+    }
+
+    fn reset_zoom(&self) {
+        self.update_view(|view| view.viewport = None);
+    }
+}
+
+fn fmt_crosshair_label(quote: &Quote, time: Option<DateTime>) -> String {
+    let time = time.map_or_else(|| MISSING.to_owned(), |time| fmt_chart_timestamp(&time));
+
+    gettext("{time}\nO {open}  H {high}  L {low}  C {close}\nVolume {volume}")
+        .replace("{time}", &time)
+        .replace("{open}", &fmt_number(Some(quote.open)))
+        .replace("{high}", &fmt_number(Some(quote.high)))
+        .replace("{low}", &fmt_number(Some(quote.low)))
+        .replace("{close}", &fmt_number(Some(quote.close)))
+        .replace("{volume}", &fmt_large_number(Some(quote.volume)))
+}
+
+fn history_range(dropdown: &DropDown) -> &'static str {
+    CHART_RANGES[dropdown.selected() as usize].0
+}
+
+/// Offers the intervals `range` can be exported at, starting out with the one
+/// its chart uses.
+fn set_history_intervals(dropdown: &DropDown, range: &str) {
+    let intervals = stox_get_chart_intervals(range);
+    let labels: Vec<&str> = intervals
+        .iter()
+        .map(|interval| {
+            HISTORY_INTERVALS
+                .iter()
+                .find(|(history_interval, _)| history_interval == interval)
+                .map_or(*interval, |(_, label)| *label)
+        })
+        .collect();
+
+    dropdown.set_model(Some(&StringList::new(&labels)));
+    dropdown.set_selected(
+        intervals
+            .iter()
+            .position(|interval| *interval == stox_get_chart_interval(range))
+            .unwrap_or_default() as u32,
+    );
+}
+
+/// Lists the symbols found for `query` in `results`, unless `entry` has moved
+/// on to another query by the time they arrive.
+fn search_comparisons(entry: &SearchEntry, results: &ListBox, query: String) {
+    let (sender, receiver) = MainContext::channel(PRIORITY_DEFAULT);
+
+    std::thread::spawn(clone!(@strong query => move || {
+        // Nobody is listening anymore if the popover was closed
+        let _ = sender.send(stox_search_symbol(&query));
+    }));
+
+    receiver.attach(
+        None,
+        clone!(
+            @weak entry, @weak results =>
+            @default-return Continue(false),
+            move |quotes| {
+                // Another search was started in the meantime
+                if entry.text() != query {
+                    return Continue(false);
+                }
+
+                for quote in quotes.iter().flatten() {
+                    let label = Label::builder()
+                        .label(format!("{}  {}", quote.symbol, quote.short_name))
+                        .halign(Align::Start)
+                        .ellipsize(pango::EllipsizeMode::End)
+                        .max_width_chars(30)
+                        .build();
+
+                    let row = ListBoxRow::builder()
+                        .child(&label)
+                        .name(&quote.symbol)
+                        .build();
+                    results.append(&row);
+                }
+
+                Continue(false)
+            }
+        ),
+    );
 }
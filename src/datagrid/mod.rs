@@ -1,3 +1,4 @@
+mod chart;
 mod imp;
 
 use std::cell::RefCell;
@@ -6,12 +7,14 @@ use std::sync::{Arc, Mutex};
 use glib::subclass::types::ObjectSubclassIsExt;
 
 use gtk4::glib::*;
-use gtk4::traits::WidgetExt;
+use gtk4::traits::{ButtonExt, WidgetExt};
 use gtk4::*;
 
 use lazy_static::lazy_static;
 
-use crate::data_helper::{stox_get_datagrid_info, stox_get_quotes};
+use crate::data_helper::stox_get_datagrid_info;
+use crate::error::StoxError;
+use crate::format_helper::*;
 
 use gettextrs::gettext;
 
@@ -49,6 +52,10 @@ macro_rules! pixel_width {
     };
 }
 
+/// How often outdated values are fetched again, in case the server comes
+/// back without the system noticing a network change.
+const RETRY_INTERVAL: u32 = 30;
+
 const ELLIPSIS: &str = "\u{2026}";
 
 fn set_label_with_max_width(label: &Label, text: &str, max_width: i32) -> i32 {
@@ -128,19 +135,8 @@ impl StoxDataGrid {
         let symbol = RefCell::new(symbol);
 
         std::thread::spawn(clone!(
-            @strong symbol => move || match stox_get_datagrid_info(&symbol.borrow()) {
-                Ok((main_info, extended_info, stats_info)) => {
-                    let quotes = stox_get_quotes(symbol.borrow().to_string(), "1d");
-                    if quotes.is_err() {
-                        sender.send(None).unwrap();
-                        return;
-                    }
-
-                    sender
-                        .send(Some((main_info, extended_info, stats_info, quotes.unwrap())))
-                        .unwrap()
-                }
-                Err(_) => sender.send(None).unwrap(),
+            @strong symbol => move || {
+                sender.send(stox_get_datagrid_info(&symbol.borrow())).unwrap()
             }
         ));
 
@@ -150,6 +146,7 @@ impl StoxDataGrid {
             name_label,
             latest_quote_label,
             market_change_label,
+            as_of_label,
             info_label,
             notebook,
             save_btn,
@@ -185,6 +182,7 @@ impl StoxDataGrid {
 
         market_change_label.set_css_classes(&[]);
         symbol_label.set_css_classes(&[]);
+        as_of_label.hide();
 
         set_label_with_max_width(
             &symbol_label,
@@ -199,11 +197,14 @@ impl StoxDataGrid {
         unsave_btn.set_sensitive(false);
         refresh_btn.set_sensitive(false);
 
+        self.cancel_retry();
+
         {
             let notebook = self.imp().notebook.borrow_mut();
-            for i in 0..notebook.n_pages() {
-                notebook.remove_page(Some(i));
+            while notebook.n_pages() > 0 {
+                notebook.remove_page(None);
             }
+            self.imp().chart_tabs.borrow_mut().clear();
         }
 
         receiver.attach(
@@ -212,11 +213,13 @@ impl StoxDataGrid {
                 let mut ok = true;
 
                 match complete_info {
-                    Some((main_info, extended_info, stats_info, quotes)) => {
-                        latest_quote_label.set_label(&main_info.last_quote);
+                    Ok((main_info, extended_info, stats_info)) => {
+                        latest_quote_label
+                            .set_label(&fmt_price(main_info.last_quote, &main_info.currency));
                         market_change_label.set_label(&format!(
                             "{} ({})",
-                            &extended_info.market_change, &extended_info.market_change_percent,
+                            fmt_change(extended_info.market_change),
+                            fmt_change_percent(extended_info.market_change_percent),
                         ));
 
                         if extended_info.market_change_neg() {
@@ -247,27 +250,63 @@ impl StoxDataGrid {
                             extended_info.exchange_name, main_info.currency
                         ));
 
-                        open_label.set_label(&stats_info.open);
-                        high_label.set_label(&stats_info.high);
-                        low_label.set_label(&stats_info.low);
-                        volume_label.set_label(&stats_info.volume);
-                        pe_ratio_label.set_label(&stats_info.pe_ratio);
-                        market_cap_label.set_label(&stats_info.market_cap);
-                        yield_label.set_label(&stats_info.dividend_yield);
-                        beta_label.set_label(&stats_info.beta);
-                        eps_label.set_label(&stats_info.eps);
-
-                        this.imp().construct_graph(main_info, extended_info, quotes);
+                        // The oldest of the values shown, they all go stale together
+                        let as_of = [
+                            main_info.as_of,
+                            extended_info.as_of,
+                            stats_info.as_ref().and_then(|stats_info| stats_info.as_of),
+                        ]
+                        .into_iter()
+                        .flatten()
+                        .min();
+
+                        if let Some(as_of) = as_of {
+                            as_of_label.set_label(&fmt_as_of(as_of));
+                            as_of_label.show();
+                        }
+                        this.imp().outdated.set(as_of.is_some());
+
+                        match stats_info {
+                            Some(stats_info) => {
+                                open_label.set_label(&fmt_number(stats_info.open));
+                                high_label.set_label(&fmt_number(stats_info.high));
+                                low_label.set_label(&fmt_number(stats_info.low));
+                                volume_label.set_label(&fmt_large_number(stats_info.volume));
+                                pe_ratio_label.set_label(&fmt_number(stats_info.pe_ratio));
+                                market_cap_label
+                                    .set_label(&fmt_large_number(stats_info.market_cap));
+                                yield_label.set_label(&fmt_percent(stats_info.dividend_yield));
+                                beta_label.set_label(&fmt_number(stats_info.beta));
+                                eps_label.set_label(&fmt_number(stats_info.eps));
+                            }
+                            None => {
+                                set_labels!(
+                                    "???",
+                                    open_label,
+                                    high_label,
+                                    low_label,
+                                    volume_label,
+                                    pe_ratio_label,
+                                    market_cap_label,
+                                    yield_label,
+                                    beta_label,
+                                    eps_label,
+                                );
+                            }
+                        }
+
+                        this.imp().construct_chart_tabs();
                     }
-                    None => {
+                    Err(err) => {
                         ok = false;
 
+                        this.imp().outdated.set(err == StoxError::Network);
+
                         set_labels!(
                             "???",
                             name_label,
                             latest_quote_label,
                             market_change_label,
-                            info_label,
                             open_label,
                             high_label,
                             low_label,
@@ -287,6 +326,8 @@ impl StoxDataGrid {
                                 - pixel_width!(name_label.layout()),
                         );
 
+                        info_label.set_label(&err.message());
+
                         notebook.append_page(
                             &Label::new(Some(&gettext("The graph could not be loaded."))),
                             Some(&Label::new(Some(&gettext("Error")))),
@@ -294,6 +335,18 @@ impl StoxDataGrid {
                     }
                 }
 
+                if this.imp().outdated.get() {
+                    let source = timeout_add_seconds_local_once(
+                        RETRY_INTERVAL,
+                        clone!(@weak this => move || {
+                            // The source is gone once it has run
+                            this.imp().retry_source.take();
+                            this.refresh_if_outdated();
+                        }),
+                    );
+                    this.imp().retry_source.replace(Some(source));
+                }
+
                 save_btn.set_sensitive(!is_default || ok);
                 unsave_btn.set_sensitive(true);
                 refresh_btn.set_sensitive(true);
@@ -306,6 +359,21 @@ impl StoxDataGrid {
 
         false
     }
+
+    /// Drops the pending retry, the values are fetched anew anyway.
+    fn cancel_retry(&self) {
+        if let Some(source) = self.imp().retry_source.take() {
+            source.remove();
+        }
+    }
+
+    /// Fetches again if what is shown is outdated, e.g. once the network is
+    /// back.
+    pub fn refresh_if_outdated(&self) {
+        if self.imp().outdated.get() {
+            self.imp().refresh_btn.borrow().emit_clicked();
+        }
+    }
 }
 
 impl Default for StoxDataGrid {
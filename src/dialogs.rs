@@ -44,3 +44,11 @@ pub fn show_unsave_stock_failed_dialog(window: &ApplicationWindow) {
         &gettext("An error occurred and the stock could not be unsaved. Try unsaving it again."),
     );
 }
+
+pub fn show_export_failed_dialog(window: &ApplicationWindow, reason: &str) {
+    show_error_dialog(
+        window,
+        &gettext("The file could not be exported ({}). Try exporting it again.")
+            .replace("{}", reason),
+    );
+}
@@ -0,0 +1,75 @@
+use std::fmt;
+
+use gettextrs::gettext;
+
+use yahoo_finance_api::YahooError;
+
+pub type Result<T> = std::result::Result<T, StoxError>;
+
+/// Why market data could not be loaded. Each case gets its own message in the
+/// UI so a typo isn't reported the same way as a lost connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StoxError {
+    UnknownSymbol,
+    Network,
+    RateLimited,
+    ParseError { field: String },
+    NoData,
+}
+
+impl StoxError {
+    pub fn parse(field: &str) -> Self {
+        Self::ParseError {
+            field: field.to_owned(),
+        }
+    }
+
+    /// A translated message for showing the error to the user.
+    pub fn message(&self) -> String {
+        match self {
+            Self::UnknownSymbol => gettext("This symbol could not be found."),
+            Self::Network => gettext("Could not connect to the server."),
+            Self::RateLimited => gettext("Too many requests, try again in a moment."),
+            Self::ParseError { field } => {
+                gettext("The server sent unexpected data ({}).").replace("{}", field)
+            }
+            Self::NoData => gettext("No data is available for this symbol."),
+        }
+    }
+}
+
+impl fmt::Display for StoxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownSymbol => write!(f, "unknown symbol"),
+            Self::Network => write!(f, "network error"),
+            Self::RateLimited => write!(f, "rate limited"),
+            Self::ParseError { field } => write!(f, "could not parse {}", field),
+            Self::NoData => write!(f, "no data"),
+        }
+    }
+}
+
+impl std::error::Error for StoxError {}
+
+impl From<reqwest::Error> for StoxError {
+    fn from(_: reqwest::Error) -> Self {
+        Self::Network
+    }
+}
+
+impl From<serde_json::Error> for StoxError {
+    fn from(_: serde_json::Error) -> Self {
+        Self::parse("response")
+    }
+}
+
+impl From<YahooError> for StoxError {
+    fn from(err: YahooError) -> Self {
+        match err {
+            YahooError::FetchFailed(_) | YahooError::ConnectionFailed(_) => Self::Network,
+            YahooError::EmptyDataSet => Self::NoData,
+            _ => Self::parse("chart"),
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use chrono::prelude::*;
+
+use gettextrs::gettext;
+
+use gtk4::glib;
+
+use rust_decimal::prelude::*;
+use rusty_money::{iso, Money};
+
+use crate::data_helper::ChartTimeLabel;
+
+/// Shown in place of any value the data source did not provide.
+pub const MISSING: &str = "N/A";
+
+const LARGE_NUMBER_SUFFIXES: [(f64, &str); 4] = [(1e12, "T"), (1e9, "B"), (1e6, "M"), (1e3, "K")];
+
+pub fn fmt_price(price: Decimal, currency: &str) -> String {
+    let price = price.round_dp(2); // limit to two decimal places
+
+    match iso::find(currency) {
+        Some(currency) => Money::from_decimal(price, currency).to_string(),
+        None => price.to_string(),
+    }
+}
+
+/// Formats a y-axis tick with as many decimals as the spacing between ticks
+/// needs, which can be more than a price usually has.
+pub fn fmt_price_tick(price: f64, step: f64, currency: &str) -> String {
+    let decimals = (-step.log10().floor()).max(0.0) as usize;
+
+    if decimals <= 2 {
+        if let Some(price) = Decimal::from_f64(price) {
+            return fmt_price(price, currency);
+        }
+    }
+
+    match iso::find(currency) {
+        Some(currency) => format!("{}{:.*}", currency.symbol, decimals, price),
+        None => format!("{:.*}", decimals, price),
+    }
+}
+
+/// Formats a y-axis tick of a chart comparing percent changes, e.g. +12.5%.
+pub fn fmt_percent_tick(change_percent: f64, step: f64) -> String {
+    let decimals = (-step.log10().floor()).max(0.0) as usize;
+
+    format!("{:+.*}%", decimals, change_percent)
+}
+
+pub fn fmt_change(change: f64) -> String {
+    format!("{:+.2}", change)
+}
+
+pub fn fmt_change_percent(change_percent: f64) -> String {
+    format!("{:+.2}%", change_percent)
+}
+
+pub fn fmt_number(value: Option<f64>) -> String {
+    match value {
+        Some(value) => format!("{:.2}", value),
+        None => MISSING.to_owned(),
+    }
+}
+
+/// Formats a fraction (0.0061) as a percentage (0.61%).
+pub fn fmt_percent(value: Option<f64>) -> String {
+    match value {
+        Some(value) => format!("{:.2}%", value * 100.0),
+        None => MISSING.to_owned(),
+    }
+}
+
+/// Shortens volumes and market caps the way Yahoo does, e.g. 2.41T.
+pub fn fmt_large_number(value: Option<u64>) -> String {
+    let value = match value {
+        Some(value) => value as f64,
+        None => return MISSING.to_owned(),
+    };
+
+    for (magnitude, suffix) in LARGE_NUMBER_SUFFIXES {
+        if value >= magnitude {
+            return format!("{:.2}{}", value / magnitude, suffix);
+        }
+    }
+
+    value.to_string()
+}
+
+/// The date and time of a chart bar, in whichever time zone `time` is in.
+pub fn fmt_chart_timestamp(time: &glib::DateTime) -> String {
+    time.format(&gettext("%b %-d %Y, %H:%M"))
+        .map(|time| time.to_string())
+        .unwrap_or_else(|_| MISSING.to_owned())
+}
+
+/// An x-axis label. Month names and the order of day and month follow the
+/// locale.
+pub fn fmt_chart_time(time: &glib::DateTime, label: ChartTimeLabel) -> String {
+    let format = match label {
+        ChartTimeLabel::Time => gettext("%H:%M"),
+        ChartTimeLabel::Day => gettext("%b %-d"),
+        ChartTimeLabel::Month => "%b".to_owned(),
+        ChartTimeLabel::Year => "%Y".to_owned(),
+    };
+
+    time.format(&format)
+        .map(|time| time.to_string())
+        .unwrap_or_else(|_| MISSING.to_owned())
+}
+
+/// The badge shown next to last known values while offline. The date is left
+/// out for values fetched today.
+pub fn fmt_as_of(as_of: DateTime<Utc>) -> String {
+    let time = glib::DateTime::from_unix_local(as_of.timestamp()).and_then(|as_of| {
+        let format = if as_of.ymd() == glib::DateTime::now_local()?.ymd() {
+            gettext("%H:%M")
+        } else {
+            gettext("%b %-d, %H:%M")
+        };
+
+        as_of.format(&format)
+    });
+
+    gettext("As of {}").replace("{}", time.as_ref().map_or(MISSING, |time| time.as_str()))
+}
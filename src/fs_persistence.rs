@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 use std::{
+    collections::HashMap,
     env,
     fs::{self, DirBuilder, OpenOptions},
     io::Write,
@@ -7,8 +8,12 @@ use std::{
     path::{Path, PathBuf},
 };
 
+use crate::indicators::Indicator;
+
 const SAVED_STOCKS_FILENAME: &str = "saved-stocks.toml";
 const SAVED_STOCKS_VERSION: u8 = 1;
+const INDICATORS_FILENAME: &str = "indicators.toml";
+const INDICATORS_VERSION: u8 = 1;
 
 #[derive(Serialize, Deserialize)]
 struct SavedStocks {
@@ -16,7 +21,14 @@ struct SavedStocks {
     symbols: Vec<String>,
 }
 
-fn persistence_disabled() -> bool {
+/// The chart indicators of every symbol that has any.
+#[derive(Serialize, Deserialize)]
+struct SavedIndicators {
+    version: u8,
+    symbols: HashMap<String, Vec<Indicator>>,
+}
+
+pub fn persistence_disabled() -> bool {
     #[cfg(not(unix))]
     return true;
 
@@ -26,7 +38,7 @@ fn persistence_disabled() -> bool {
     }
 }
 
-fn get_persistence_path() -> Result<PathBuf, anyhow::Error> {
+pub fn get_persistence_path() -> Result<PathBuf, anyhow::Error> {
     let mut data_home = env::var("XDG_DATA_HOME");
     if data_home.is_err() {
         let home = env::var("HOME")?;
@@ -107,3 +119,111 @@ pub fn read_saved_stocks() -> Result<Vec<String>, anyhow::Error> {
         }
     }
 }
+
+fn read_all_indicators(path: &Path) -> Result<HashMap<String, Vec<Indicator>>, anyhow::Error> {
+    match fs::read_to_string(path) {
+        Ok(toml_data) => {
+            let saved_indicators: SavedIndicators = toml::from_str(&toml_data)?;
+            if saved_indicators.version != INDICATORS_VERSION {
+                return Err(anyhow::anyhow!("unknown file version"));
+            }
+
+            Ok(saved_indicators.symbols)
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn write_all_indicators(
+    path: &Path,
+    symbols: HashMap<String, Vec<Indicator>>,
+) -> Result<(), anyhow::Error> {
+    let saved_indicators = SavedIndicators {
+        version: INDICATORS_VERSION,
+        symbols,
+    };
+
+    let toml_data = toml::to_string(&saved_indicators)?;
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .create(true)
+        .mode(0o600)
+        .open(path)?;
+
+    file.write_all(toml_data.as_bytes())?;
+
+    Ok(())
+}
+
+pub fn read_indicators(symbol: &str) -> Result<Vec<Indicator>, anyhow::Error> {
+    if persistence_disabled() {
+        return Ok(vec![]);
+    }
+
+    let path = get_persistence_path()?.join(INDICATORS_FILENAME);
+
+    Ok(read_all_indicators(&path)?
+        .remove(symbol)
+        .unwrap_or_default())
+}
+
+pub fn write_indicators(symbol: &str, indicators: &[Indicator]) -> Result<(), anyhow::Error> {
+    if persistence_disabled() {
+        return Ok(());
+    }
+
+    let path = get_persistence_path()?.join(INDICATORS_FILENAME);
+
+    // A file of an unknown version fails to read and is left alone
+    let mut symbols = read_all_indicators(&path)?;
+    if indicators.is_empty() {
+        symbols.remove(symbol);
+    } else {
+        symbols.insert(symbol.to_owned(), indicators.to_vec());
+    }
+
+    write_all_indicators(&path, symbols)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indicators::INDICATORS;
+
+    fn test_file(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("stox-{}-{}.toml", name, std::process::id()))
+    }
+
+    #[test]
+    fn indicators_are_read_back() {
+        let path = test_file("indicators");
+        let symbols = HashMap::from([
+            ("DEMO".to_owned(), INDICATORS.to_vec()),
+            ("AAPL".to_owned(), vec![Indicator::Sma { period: 50 }]),
+        ]);
+
+        write_all_indicators(&path, symbols.clone()).unwrap();
+        let read = read_all_indicators(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(read.unwrap(), symbols);
+    }
+
+    #[test]
+    fn indicators_of_an_unknown_version_are_ignored() {
+        let path = test_file("indicators-version");
+        fs::write(
+            &path,
+            "version = 2\n\n[symbols]\nDEMO = [{ kind = \"vwap\" }]\n",
+        )
+        .unwrap();
+
+        let read = read_all_indicators(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert!(read.is_err());
+    }
+}
@@ -0,0 +1,353 @@
+//! Writes the bars of a chart out for spreadsheets and notebooks. Timestamps
+//! always carry their offset from UTC, whichever clock they are written in.
+//!
+//! Values Yahoo left out of a bar are written as an empty cell in CSV and as
+//! `null` in JSON, rather than as the 0 they are read as. A 0 Yahoo did send,
+//! e.g. the volume of an index, is written as is.
+
+use std::io::{self, Write};
+
+use gtk4::glib;
+
+use serde::Serialize;
+
+use crate::data_helper::{ChartInfo, MarketSession};
+
+pub const HISTORY_COLUMNS: [&str; 7] = [
+    "timestamp",
+    "open",
+    "high",
+    "low",
+    "close",
+    "adjclose",
+    "volume",
+];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum HistoryFormat {
+    Csv,
+    JsonLines,
+}
+
+impl HistoryFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "csv" => Some(Self::Csv),
+            "jsonl" => Some(Self::JsonLines),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::JsonLines => "jsonl",
+        }
+    }
+}
+
+/// The clock the timestamps are written in.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum HistoryTimeZone {
+    Utc,
+    /// That of the exchange the symbol trades on, e.g. -05:00 or -04:00 in
+    /// New York depending on daylight saving time
+    Exchange,
+}
+
+impl HistoryTimeZone {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "utc" => Some(Self::Utc),
+            "exchange" => Some(Self::Exchange),
+            _ => None,
+        }
+    }
+}
+
+/// Which trading sessions the bars are taken from.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum HistorySessions {
+    /// Regular trading hours only, like the bars of daily and longer
+    /// intervals
+    Regular,
+    /// Pre and post-market trading too, where the interval is short enough
+    /// to have them
+    All,
+}
+
+impl HistorySessions {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "regular" => Some(Self::Regular),
+            "all" => Some(Self::All),
+            _ => None,
+        }
+    }
+}
+
+/// One bar, with its fields in the order of `HISTORY_COLUMNS`. Bars without
+/// a close are never sent, every other value may be missing.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct HistoryRow {
+    /// ISO 8601, e.g. 2023-01-09T09:30:00-05:00
+    pub timestamp: String,
+    pub open: Option<f64>,
+    pub high: Option<f64>,
+    pub low: Option<f64>,
+    pub close: f64,
+    pub adjclose: Option<f64>,
+    pub volume: Option<u64>,
+}
+
+/// A row per bar of `info` in `sessions`, oldest first.
+pub fn history_rows(
+    info: &ChartInfo,
+    time_zone: HistoryTimeZone,
+    sessions: HistorySessions,
+) -> io::Result<Vec<HistoryRow>> {
+    let zone = match time_zone {
+        HistoryTimeZone::Utc => glib::TimeZone::utc(),
+        HistoryTimeZone::Exchange => info.exchange_time_zone(),
+    };
+
+    info.quotes
+        .iter()
+        .enumerate()
+        .filter(|(_, quote)| {
+            sessions == HistorySessions::All
+                || !matches!(
+                    info.session_at(quote.timestamp),
+                    Some(MarketSession::Pre | MarketSession::Post)
+                )
+        })
+        .map(|(i, quote)| {
+            let timestamp = glib::DateTime::from_unix_utc(quote.timestamp as i64)
+                .and_then(|time| time.to_timezone(&zone))
+                .and_then(|time| time.format("%Y-%m-%dT%H:%M:%S%:z"))
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+            let gaps = info.gaps.get(i).copied().unwrap_or_default();
+
+            Ok(HistoryRow {
+                timestamp: timestamp.to_string(),
+                open: (!gaps.open).then_some(quote.open),
+                high: (!gaps.high).then_some(quote.high),
+                low: (!gaps.low).then_some(quote.low),
+                close: quote.close,
+                adjclose: (!gaps.adjclose).then_some(quote.adjclose),
+                volume: (!gaps.volume).then_some(quote.volume),
+            })
+        })
+        .collect()
+}
+
+/// Writes the rows of `info` to `writer`, see `history_rows`.
+pub fn write_history(
+    writer: &mut impl Write,
+    info: &ChartInfo,
+    format: HistoryFormat,
+    time_zone: HistoryTimeZone,
+    sessions: HistorySessions,
+) -> io::Result<()> {
+    if format == HistoryFormat::Csv {
+        writeln!(writer, "{}", HISTORY_COLUMNS.join(","))?;
+    }
+
+    for row in history_rows(info, time_zone, sessions)? {
+        match format {
+            HistoryFormat::Csv => writeln!(
+                writer,
+                "{},{},{},{},{},{},{}",
+                row.timestamp,
+                csv_cell(row.open),
+                csv_cell(row.high),
+                csv_cell(row.low),
+                row.close,
+                csv_cell(row.adjclose),
+                csv_cell(row.volume)
+            )?,
+            HistoryFormat::JsonLines => {
+                serde_json::to_writer(&mut *writer, &row)?;
+                writeln!(writer)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// A missing value is an empty cell.
+fn csv_cell(value: Option<impl ToString>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use yahoo_finance_api::Quote;
+
+    use crate::data_helper::{QuoteGaps, TradingPeriod};
+
+    use super::*;
+
+    // 2023-03-06 14:30 UTC
+    const TIMESTAMP: u64 = 1678113000;
+
+    fn quote(timestamp: u64, open: f64, adjclose: f64, volume: u64) -> Quote {
+        Quote {
+            timestamp,
+            open,
+            high: open + 1.0,
+            low: open - 1.0,
+            volume,
+            close: open + 0.5,
+            adjclose,
+        }
+    }
+
+    /// Tokyo has no daylight saving time, so its offset is always the same.
+    fn info(quotes: Vec<Quote>) -> ChartInfo {
+        ChartInfo {
+            quotes,
+            currency: "JPY".to_owned(),
+            timezone: "Asia/Tokyo".to_owned(),
+            utc_offset: 9 * 60 * 60,
+            previous_close: None,
+            trading_periods: vec![],
+            gaps: vec![],
+            as_of: None,
+        }
+    }
+
+    fn written(info: &ChartInfo, format: HistoryFormat, time_zone: HistoryTimeZone) -> String {
+        let mut buffer = vec![];
+        write_history(
+            &mut buffer,
+            info,
+            format,
+            time_zone,
+            HistorySessions::Regular,
+        )
+        .unwrap();
+
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn csv_has_a_header_and_a_line_per_bar() {
+        let info = info(vec![
+            quote(TIMESTAMP, 100.0, 100.25, 1000),
+            quote(TIMESTAMP + 3600, 101.0, 101.25, 0),
+        ]);
+
+        assert_eq!(
+            written(&info, HistoryFormat::Csv, HistoryTimeZone::Utc),
+            "timestamp,open,high,low,close,adjclose,volume\n\
+             2023-03-06T14:30:00+00:00,100,101,99,100.5,100.25,1000\n\
+             2023-03-06T15:30:00+00:00,101,102,100,101.5,101.25,0\n"
+        );
+    }
+
+    #[test]
+    fn json_lines_keep_the_column_order() {
+        let info = info(vec![quote(TIMESTAMP, 100.0, 100.25, 1000)]);
+
+        assert_eq!(
+            written(&info, HistoryFormat::JsonLines, HistoryTimeZone::Utc),
+            "{\"timestamp\":\"2023-03-06T14:30:00+00:00\",\"open\":100.0,\"high\":101.0,\
+             \"low\":99.0,\"close\":100.5,\"adjclose\":100.25,\"volume\":1000}\n"
+        );
+    }
+
+    #[test]
+    fn exchange_time_carries_its_offset() {
+        let info = info(vec![quote(TIMESTAMP, 100.0, 100.0, 0)]);
+
+        let utc = history_rows(&info, HistoryTimeZone::Utc, HistorySessions::Regular).unwrap();
+        let exchange =
+            history_rows(&info, HistoryTimeZone::Exchange, HistorySessions::Regular).unwrap();
+
+        assert_eq!(utc[0].timestamp, "2023-03-06T14:30:00+00:00");
+        assert_eq!(exchange[0].timestamp, "2023-03-06T23:30:00+09:00");
+    }
+
+    #[test]
+    fn missing_values_are_left_empty() {
+        let mut info = info(vec![
+            quote(TIMESTAMP, 0.0, 0.0, 0),
+            quote(TIMESTAMP + 3600, 0.0, 0.0, 0),
+        ]);
+        info.gaps = vec![
+            QuoteGaps {
+                open: true,
+                high: true,
+                low: true,
+                volume: true,
+                adjclose: true,
+            },
+            QuoteGaps {
+                adjclose: true,
+                ..QuoteGaps::default()
+            },
+        ];
+
+        assert_eq!(
+            written(&info, HistoryFormat::Csv, HistoryTimeZone::Utc),
+            "timestamp,open,high,low,close,adjclose,volume\n\
+             2023-03-06T14:30:00+00:00,,,,0.5,,\n\
+             2023-03-06T15:30:00+00:00,0,1,-1,0.5,,0\n"
+        );
+        assert_eq!(
+            written(&info, HistoryFormat::JsonLines, HistoryTimeZone::Utc)
+                .lines()
+                .next(),
+            Some(
+                "{\"timestamp\":\"2023-03-06T14:30:00+00:00\",\"open\":null,\"high\":null,\
+                 \"low\":null,\"close\":0.5,\"adjclose\":null,\"volume\":null}"
+            )
+        );
+    }
+
+    #[test]
+    fn real_zeros_are_kept() {
+        let info = info(vec![quote(TIMESTAMP, 1.0, 0.0, 0)]);
+
+        let rows = history_rows(&info, HistoryTimeZone::Utc, HistorySessions::Regular).unwrap();
+
+        assert_eq!(rows[0].adjclose, Some(0.0));
+        assert_eq!(rows[0].volume, Some(0));
+    }
+
+    #[test]
+    fn extended_hours_are_only_left_out_of_regular_sessions() {
+        let mut info = info(vec![
+            quote(TIMESTAMP - 3600, 99.0, 99.0, 10),
+            quote(TIMESTAMP, 100.0, 100.0, 10),
+            quote(TIMESTAMP + 3600, 101.0, 101.0, 10),
+        ]);
+        info.trading_periods = vec![
+            TradingPeriod {
+                session: MarketSession::Pre,
+                start: TIMESTAMP - 3600,
+                end: TIMESTAMP,
+            },
+            TradingPeriod {
+                session: MarketSession::Regular,
+                start: TIMESTAMP,
+                end: TIMESTAMP + 3600,
+            },
+            TradingPeriod {
+                session: MarketSession::Post,
+                start: TIMESTAMP + 3600,
+                end: TIMESTAMP + 7200,
+            },
+        ];
+
+        let regular = history_rows(&info, HistoryTimeZone::Utc, HistorySessions::Regular).unwrap();
+        let all = history_rows(&info, HistoryTimeZone::Utc, HistorySessions::All).unwrap();
+
+        assert_eq!(regular.len(), 1);
+        assert_eq!(regular[0].open, Some(100.0));
+        assert_eq!(all.len(), 3);
+    }
+}
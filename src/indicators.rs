@@ -0,0 +1,618 @@
+//! Technical indicators computed from the bars of a chart. Every series lines
+//! up with the bars it was computed from, with `None` wherever there is not
+//! enough history yet.
+
+use serde::{Deserialize, Serialize};
+
+use yahoo_finance_api::Quote;
+
+/// How many standard deviations the Bollinger Bands keep from their average
+pub const BOLLINGER_WIDTH: f64 = 2.0;
+pub const MACD_FAST_PERIOD: usize = 12;
+pub const MACD_SLOW_PERIOD: usize = 26;
+pub const MACD_SIGNAL_PERIOD: usize = 9;
+/// How many bars of %K the Stochastic's %D averages
+pub const STOCHASTIC_D_PERIOD: usize = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Indicator {
+    Sma { period: usize },
+    Ema { period: usize },
+    Bollinger { period: usize },
+    Vwap,
+    Rsi { period: usize },
+    Macd,
+    Stochastic { period: usize },
+}
+
+/// Every kind of indicator with its usual settings, in the order they are
+/// offered.
+pub const INDICATORS: [Indicator; 7] = [
+    Indicator::Sma { period: 20 },
+    Indicator::Ema { period: 20 },
+    Indicator::Bollinger { period: 20 },
+    Indicator::Vwap,
+    Indicator::Rsi { period: 14 },
+    Indicator::Macd,
+    Indicator::Stochastic { period: 14 },
+];
+
+/// What an indicator draws, every series as long as the bars.
+pub struct IndicatorSeries {
+    pub lines: Vec<Vec<Option<f64>>>,
+    pub histogram: Option<Vec<Option<f64>>>,
+}
+
+impl Indicator {
+    /// The short name shown in the chart legend, e.g. "SMA 20".
+    pub fn name(&self) -> String {
+        match self {
+            Self::Sma { period } => format!("SMA {}", period),
+            Self::Ema { period } => format!("EMA {}", period),
+            Self::Bollinger { period } => format!("BB {} {}", period, BOLLINGER_WIDTH),
+            Self::Vwap => "VWAP".to_owned(),
+            Self::Rsi { period } => format!("RSI {}", period),
+            Self::Macd => format!(
+                "MACD {} {} {}",
+                MACD_FAST_PERIOD, MACD_SLOW_PERIOD, MACD_SIGNAL_PERIOD
+            ),
+            Self::Stochastic { period } => format!("Stoch {} {}", period, STOCHASTIC_D_PERIOD),
+        }
+    }
+
+    pub fn period(&self) -> Option<usize> {
+        match *self {
+            Self::Sma { period }
+            | Self::Ema { period }
+            | Self::Bollinger { period }
+            | Self::Rsi { period }
+            | Self::Stochastic { period } => Some(period),
+            Self::Vwap | Self::Macd => None,
+        }
+    }
+
+    /// The same kind of indicator over `period` bars, if it has a period.
+    pub fn with_period(self, period: usize) -> Self {
+        match self {
+            Self::Sma { .. } => Self::Sma { period },
+            Self::Ema { .. } => Self::Ema { period },
+            Self::Bollinger { .. } => Self::Bollinger { period },
+            Self::Rsi { .. } => Self::Rsi { period },
+            Self::Stochastic { .. } => Self::Stochastic { period },
+            Self::Vwap | Self::Macd => self,
+        }
+    }
+
+    /// Oscillators get a pane of their own under the prices, the others are
+    /// drawn over them.
+    pub fn is_oscillator(&self) -> bool {
+        matches!(
+            self,
+            Self::Rsi { .. } | Self::Macd | Self::Stochastic { .. }
+        )
+    }
+
+    /// The fixed range of an oscillator's values, if it has one.
+    pub fn bounds(&self) -> Option<(f64, f64)> {
+        match self {
+            Self::Rsi { .. } | Self::Stochastic { .. } => Some((0.0, 100.0)),
+            _ => None,
+        }
+    }
+
+    /// The levels an oscillator is read against, e.g. overbought and
+    /// oversold.
+    pub fn levels(&self) -> &'static [f64] {
+        match self {
+            Self::Rsi { .. } => &[30.0, 70.0],
+            Self::Stochastic { .. } => &[20.0, 80.0],
+            Self::Macd => &[0.0],
+            _ => &[],
+        }
+    }
+
+    /// Works out the indicator for every bar. VWAP starts over on the bars
+    /// `new_session` picks out.
+    pub fn series(&self, quotes: &[Quote], new_session: impl Fn(usize) -> bool) -> IndicatorSeries {
+        let closes: Vec<f64> = quotes.iter().map(|quote| quote.close).collect();
+
+        let lines = match *self {
+            Self::Sma { period } => vec![sma(&closes, period)],
+            Self::Ema { period } => vec![ema(&closes, period)],
+            Self::Bollinger { period } => {
+                let bands = bollinger(&closes, period, BOLLINGER_WIDTH);
+
+                vec![
+                    bands
+                        .iter()
+                        .map(|band| band.map(|band| band.lower))
+                        .collect(),
+                    bands
+                        .iter()
+                        .map(|band| band.map(|band| band.middle))
+                        .collect(),
+                    bands
+                        .iter()
+                        .map(|band| band.map(|band| band.upper))
+                        .collect(),
+                ]
+            }
+            Self::Vwap => vec![vwap(quotes, new_session)],
+            Self::Rsi { period } => vec![rsi(&closes, period)],
+            Self::Macd => {
+                let macd = macd(
+                    &closes,
+                    MACD_FAST_PERIOD,
+                    MACD_SLOW_PERIOD,
+                    MACD_SIGNAL_PERIOD,
+                );
+
+                return IndicatorSeries {
+                    lines: vec![
+                        macd.iter().map(|macd| macd.map(|macd| macd.macd)).collect(),
+                        macd.iter()
+                            .map(|macd| macd.and_then(|macd| macd.signal))
+                            .collect(),
+                    ],
+                    histogram: Some(
+                        macd.iter()
+                            .map(|macd| macd.and_then(|macd| macd.histogram))
+                            .collect(),
+                    ),
+                };
+            }
+            Self::Stochastic { period } => {
+                let stochastic = stochastic(quotes, period, STOCHASTIC_D_PERIOD);
+
+                vec![
+                    stochastic
+                        .iter()
+                        .map(|stochastic| stochastic.map(|stochastic| stochastic.k))
+                        .collect(),
+                    stochastic
+                        .iter()
+                        .map(|stochastic| stochastic.and_then(|stochastic| stochastic.d))
+                        .collect(),
+                ]
+            }
+        };
+
+        IndicatorSeries {
+            lines,
+            histogram: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Band {
+    pub lower: f64,
+    pub middle: f64,
+    pub upper: f64,
+}
+
+/// The simple moving average, the mean of the last `period` values.
+pub fn sma(values: &[f64], period: usize) -> Vec<Option<f64>> {
+    if period == 0 {
+        return vec![None; values.len()];
+    }
+
+    let mut sum = 0.0;
+
+    values
+        .iter()
+        .enumerate()
+        .map(|(i, value)| {
+            sum += value;
+            if i >= period {
+                sum -= values[i - period];
+            }
+
+            (i + 1 >= period).then(|| sum / period as f64)
+        })
+        .collect()
+}
+
+/// The exponential moving average, which weighs recent values more. It
+/// starts out from the simple average of the first `period` values.
+pub fn ema(values: &[f64], period: usize) -> Vec<Option<f64>> {
+    if period == 0 || values.len() < period {
+        return vec![None; values.len()];
+    }
+
+    let alpha = 2.0 / (period as f64 + 1.0);
+    let mut average = values[..period].iter().sum::<f64>() / period as f64;
+
+    let mut ema = vec![None; period - 1];
+    ema.push(Some(average));
+
+    for value in &values[period..] {
+        average += alpha * (value - average);
+        ema.push(Some(average));
+    }
+
+    ema
+}
+
+/// The simple moving average with bands `width` standard deviations above and
+/// below it.
+pub fn bollinger(values: &[f64], period: usize, width: f64) -> Vec<Option<Band>> {
+    sma(values, period)
+        .into_iter()
+        .enumerate()
+        .map(|(i, middle)| {
+            let middle = middle?;
+            let window = &values[i + 1 - period..=i];
+            let variance = window
+                .iter()
+                .map(|value| (value - middle).powi(2))
+                .sum::<f64>()
+                / period as f64;
+            let deviation = variance.sqrt() * width;
+
+            Some(Band {
+                lower: middle - deviation,
+                middle,
+                upper: middle + deviation,
+            })
+        })
+        .collect()
+}
+
+/// The volume weighted average of the typical price (the mean of high, low
+/// and close), since the last bar `new_session` picks out or else since the
+/// first bar.
+pub fn vwap(quotes: &[Quote], new_session: impl Fn(usize) -> bool) -> Vec<Option<f64>> {
+    let mut price_volume = 0.0;
+    let mut volume = 0.0;
+
+    quotes
+        .iter()
+        .enumerate()
+        .map(|(i, quote)| {
+            if new_session(i) {
+                price_volume = 0.0;
+                volume = 0.0;
+            }
+
+            let typical_price = (quote.high + quote.low + quote.close) / 3.0;
+            price_volume += typical_price * quote.volume as f64;
+            volume += quote.volume as f64;
+
+            (volume > 0.0).then(|| price_volume / volume)
+        })
+        .collect()
+}
+
+/// The Relative Strength Index, how much of the recent movement was upwards
+/// on a scale of 0 to 100. Gains and losses are averaged the way Wilder did,
+/// starting out from their simple average over the first `period` changes.
+pub fn rsi(values: &[f64], period: usize) -> Vec<Option<f64>> {
+    if period == 0 || values.len() <= period {
+        return vec![None; values.len()];
+    }
+
+    let changes: Vec<f64> = values.windows(2).map(|pair| pair[1] - pair[0]).collect();
+    let mut gain = changes[..period]
+        .iter()
+        .map(|change| change.max(0.0))
+        .sum::<f64>()
+        / period as f64;
+    let mut loss = changes[..period]
+        .iter()
+        .map(|change| (-change).max(0.0))
+        .sum::<f64>()
+        / period as f64;
+
+    let mut rsi = vec![None; period];
+    rsi.push(Some(relative_strength(gain, loss)));
+
+    for change in &changes[period..] {
+        gain = (gain * (period - 1) as f64 + change.max(0.0)) / period as f64;
+        loss = (loss * (period - 1) as f64 + (-change).max(0.0)) / period as f64;
+        rsi.push(Some(relative_strength(gain, loss)));
+    }
+
+    rsi
+}
+
+fn relative_strength(gain: f64, loss: f64) -> f64 {
+    if loss == 0.0 {
+        // Flat prices are neither strong nor weak
+        return if gain == 0.0 { 50.0 } else { 100.0 };
+    }
+
+    100.0 - 100.0 / (1.0 + gain / loss)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Macd {
+    pub macd: f64,
+    pub signal: Option<f64>,
+    pub histogram: Option<f64>,
+}
+
+/// The Moving Average Convergence Divergence, the fast EMA less the slow one,
+/// with an EMA of that as the signal line and the difference between the two
+/// as the histogram.
+pub fn macd(values: &[f64], fast: usize, slow: usize, signal: usize) -> Vec<Option<Macd>> {
+    let macd: Vec<Option<f64>> = ema(values, fast)
+        .into_iter()
+        .zip(ema(values, slow))
+        .map(|(fast, slow)| Some(fast? - slow?))
+        .collect();
+    let signal = ema_of_defined(&macd, signal);
+
+    macd.into_iter()
+        .zip(signal)
+        .map(|(macd, signal)| {
+            let macd = macd?;
+
+            Some(Macd {
+                macd,
+                signal,
+                histogram: signal.map(|signal| macd - signal),
+            })
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stochastic {
+    pub k: f64,
+    pub d: Option<f64>,
+}
+
+/// The Stochastic Oscillator. %K is where the close is within the range of
+/// the last `k_period` bars on a scale of 0 to 100, %D its simple average
+/// over `d_period` bars.
+pub fn stochastic(quotes: &[Quote], k_period: usize, d_period: usize) -> Vec<Option<Stochastic>> {
+    if k_period == 0 {
+        return vec![None; quotes.len()];
+    }
+
+    let k: Vec<Option<f64>> = (0..quotes.len())
+        .map(|i| {
+            let window = &quotes[(i + 1).checked_sub(k_period)?..=i];
+            let low = window
+                .iter()
+                .map(|quote| quote.low)
+                .fold(f64::INFINITY, f64::min);
+            let high = window
+                .iter()
+                .map(|quote| quote.high)
+                .fold(f64::NEG_INFINITY, f64::max);
+
+            Some(if high > low {
+                (quotes[i].close - low) / (high - low) * 100.0
+            } else {
+                50.0
+            })
+        })
+        .collect();
+    let d = sma_of_defined(&k, d_period);
+
+    k.into_iter()
+        .zip(d)
+        .map(|(k, d)| Some(Stochastic { k: k?, d }))
+        .collect()
+}
+
+/// An EMA of a series that only starts partway in.
+fn ema_of_defined(series: &[Option<f64>], period: usize) -> Vec<Option<f64>> {
+    of_defined(series, |values| ema(values, period))
+}
+
+/// An SMA of a series that only starts partway in.
+fn sma_of_defined(series: &[Option<f64>], period: usize) -> Vec<Option<f64>> {
+    of_defined(series, |values| sma(values, period))
+}
+
+fn of_defined(
+    series: &[Option<f64>],
+    indicator: impl Fn(&[f64]) -> Vec<Option<f64>>,
+) -> Vec<Option<f64>> {
+    let start = series
+        .iter()
+        .position(Option::is_some)
+        .unwrap_or(series.len());
+    let values: Vec<f64> = series[start..].iter().map_while(|value| *value).collect();
+
+    let mut result = vec![None; start];
+    result.extend(indicator(&values));
+    result.resize(series.len(), None);
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote(high: f64, low: f64, close: f64, volume: u64) -> Quote {
+        Quote {
+            timestamp: 0,
+            open: close,
+            high,
+            low,
+            volume,
+            close,
+            adjclose: close,
+        }
+    }
+
+    fn assert_series(actual: &[Option<f64>], expected: &[Option<f64>]) {
+        assert_eq!(actual.len(), expected.len(), "{:?}", actual);
+        for (actual_value, expected_value) in actual.iter().zip(expected) {
+            match (actual_value, expected_value) {
+                (Some(a), Some(e)) => assert!((a - e).abs() < 1e-9, "{:?}", actual),
+                (None, None) => {}
+                _ => panic!("{:?} is not {:?}", actual, expected),
+            }
+        }
+    }
+
+    #[test]
+    fn sma_averages_the_last_period_values() {
+        assert_series(
+            &sma(&[1.0, 2.0, 3.0, 4.0, 5.0], 3),
+            &[None, None, Some(2.0), Some(3.0), Some(4.0)],
+        );
+    }
+
+    #[test]
+    fn ema_starts_from_the_simple_average() {
+        assert_series(
+            &ema(&[2.0, 4.0, 6.0, 8.0, 12.0], 3),
+            &[None, None, Some(4.0), Some(6.0), Some(9.0)],
+        );
+    }
+
+    #[test]
+    fn bollinger_uses_the_population_deviation() {
+        let bands = bollinger(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0], 8, 2.0);
+
+        assert!(bands[..7].iter().all(Option::is_none));
+        assert_eq!(
+            bands[7],
+            Some(Band {
+                lower: 1.0,
+                middle: 5.0,
+                upper: 9.0,
+            })
+        );
+    }
+
+    #[test]
+    fn vwap_starts_over_with_each_session() {
+        let quotes = [
+            quote(3.0, 1.0, 2.0, 10),
+            quote(6.0, 3.0, 3.0, 30),
+            quote(5.0, 5.0, 5.0, 0),
+            quote(3.0, 3.0, 3.0, 10),
+        ];
+
+        assert_series(
+            &vwap(&quotes, |i| i == 2),
+            &[Some(2.0), Some(3.5), None, Some(3.0)],
+        );
+    }
+
+    #[test]
+    fn rsi_smooths_like_wilder() {
+        assert_series(
+            &rsi(&[1.0, 2.0, 1.0, 2.0, 3.0], 2),
+            &[None, None, Some(50.0), Some(75.0), Some(87.5)],
+        );
+    }
+
+    #[test]
+    fn rsi_of_flat_prices_is_50() {
+        assert_series(
+            &rsi(&[5.0; 5], 3),
+            &[None, None, None, Some(50.0), Some(50.0)],
+        );
+        assert_series(
+            &rsi(&[1.0, 2.0, 3.0, 4.0], 2),
+            &[None, None, Some(100.0), Some(100.0)],
+        );
+    }
+
+    #[test]
+    fn macd_lines_up_with_the_bars() {
+        // The EMAs of a straight line lag it by a constant (period - 1) / 2,
+        // so the MACD settles at 12.5 - 5.5 right away.
+        let values: Vec<f64> = (0..40).map(f64::from).collect();
+        let macd = macd(&values, 12, 26, 9);
+
+        assert_eq!(macd.len(), values.len());
+        assert!(macd[..25].iter().all(Option::is_none));
+        for macd in &macd[25..33] {
+            let macd = macd.unwrap();
+            assert!((macd.macd - 7.0).abs() < 1e-9);
+            assert_eq!(macd.signal, None);
+            assert_eq!(macd.histogram, None);
+        }
+        for macd in &macd[33..] {
+            let macd = macd.unwrap();
+            assert!((macd.signal.unwrap() - 7.0).abs() < 1e-9);
+            assert!(macd.histogram.unwrap().abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn of_defined_keeps_the_offset() {
+        assert_series(
+            &ema_of_defined(&[None, None, Some(1.0), Some(2.0), Some(3.0)], 2),
+            &[None, None, None, Some(1.5), Some(2.5)],
+        );
+        assert_series(
+            &sma_of_defined(&[None, Some(1.0), Some(2.0), None], 1),
+            &[None, Some(1.0), Some(2.0), None],
+        );
+        assert_series(&sma_of_defined(&[None, None], 1), &[None, None]);
+    }
+
+    #[test]
+    fn stochastic_places_the_close_in_the_range() {
+        let quotes = [
+            quote(10.0, 8.0, 9.0, 0),
+            quote(12.0, 9.0, 11.0, 0),
+            quote(11.0, 7.0, 8.0, 0),
+            quote(13.0, 10.0, 13.0, 0),
+        ];
+
+        assert_eq!(
+            stochastic(&quotes, 3, 2),
+            [
+                None,
+                None,
+                Some(Stochastic { k: 20.0, d: None }),
+                Some(Stochastic {
+                    k: 100.0,
+                    d: Some(60.0),
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn stochastic_of_a_flat_range_is_50() {
+        let quotes = vec![quote(5.0, 5.0, 5.0, 0); 3];
+
+        assert_eq!(
+            stochastic(&quotes, 2, 2)[2],
+            Some(Stochastic {
+                k: 50.0,
+                d: Some(50.0),
+            })
+        );
+    }
+
+    #[test]
+    fn zero_period_gives_nothing() {
+        let values = [1.0, 2.0, 3.0];
+        let quotes = vec![quote(2.0, 1.0, 1.5, 1); 3];
+
+        assert_series(&sma(&values, 0), &[None; 3]);
+        assert_series(&ema(&values, 0), &[None; 3]);
+        assert_eq!(bollinger(&values, 0, 2.0), [None; 3]);
+        assert_series(&rsi(&values, 0), &[None; 3]);
+        assert_eq!(stochastic(&quotes, 0, 3), [None; 3]);
+    }
+
+    #[test]
+    fn too_little_history_gives_nothing() {
+        let values = [1.0, 2.0, 3.0];
+        let quotes = vec![quote(2.0, 1.0, 1.5, 1); 3];
+
+        assert_series(&sma(&values, 4), &[None; 3]);
+        assert_series(&ema(&values, 4), &[None; 3]);
+        assert_eq!(bollinger(&values, 4, 2.0), [None; 3]);
+        // RSI needs `period` changes, one more value than that
+        assert_series(&rsi(&values, 3), &[None; 3]);
+        assert_eq!(macd(&values, 12, 26, 9), [None; 3]);
+        assert_eq!(stochastic(&quotes, 4, 3), [None; 3]);
+    }
+}
@@ -1,12 +1,19 @@
+mod cli;
 mod config;
 mod data_helper;
 mod datagrid;
 mod dialogs;
+mod error;
+mod format_helper;
 mod fs_persistence;
+mod history_export;
+mod indicators;
+mod providers;
+mod refresh_scheduler;
 mod sidebar_item;
 
 use config::*;
-use data_helper::stox_search_symbol;
+use data_helper::{stox_is_offline, stox_search_symbol, stox_set_offline};
 use datagrid::StoxDataGrid;
 use fs_persistence::{read_saved_stocks, write_saved_stocks};
 use sidebar_item::StoxSidebarItem;
@@ -26,6 +33,8 @@ use std::rc::Rc;
 use std::{panic, sync::*};
 
 pub const APP_ID: &str = "org.itzswirlz.stox";
+/// What warnings are logged under
+pub const LOG_DOMAIN: &str = "stox";
 
 fn main() {
     setlocale(LocaleCategory::LcAll, "");
@@ -35,6 +44,12 @@ fn main() {
     textdomain(GETTEXT_PACKAGE)
         .unwrap_or_else(|_| panic!("Unable to switch to text domain {}", GETTEXT_PACKAGE));
 
+    // Commands like `stox export` run without starting the app
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(code) = cli::run(&args) {
+        std::process::exit(code);
+    }
+
     // Register and include resources
     // Taken from gnome-tour
     let res = gio::Resource::load(config::RESOURCES_FILE).expect("Could not load resources");
@@ -159,6 +174,19 @@ fn build_ui(app: &Application, default_symbol: Option<String>) {
             .stat_data_label {
                 font-weight: 350;
             }
+
+            .chart_crosshair_label {
+                background-color: rgba(0, 0, 0, 0.75);
+                color: white;
+                padding: 4px 6px;
+                border-radius: 4px;
+                font-size: smaller;
+            }
+
+            .as_of {
+                font-size: smaller;
+                opacity: 0.7;
+            }
         ",
     );
 
@@ -287,6 +315,25 @@ fn build_ui(app: &Application, default_symbol: Option<String>) {
         .min_content_height(800)
         .build();
 
+    // Rows scrolled out of view stop refreshing until they come back
+    let update_off_screen_rows = Rc::new(clone!(
+        @weak scroll_window, @strong sidebar_symbols => move || {
+            let height = scroll_window.height() as f32;
+
+            for item in sidebar_symbols.lock().unwrap().iter() {
+                if let Some(bounds) = item.compute_bounds(&scroll_window) {
+                    item.set_off_screen(bounds.y() + bounds.height() < 0.0 || bounds.y() > height);
+                }
+            }
+        }
+    ));
+
+    let adjustment = scroll_window.vadjustment();
+    adjustment.connect_value_changed(clone!(@strong update_off_screen_rows => move |_| {
+        update_off_screen_rows();
+    }));
+    adjustment.connect_changed(move |_| update_off_screen_rows());
+
     b.append(&scroll_window);
 
     let datagrid = RefCell::new(StoxDataGrid::new());
@@ -373,6 +420,24 @@ fn build_ui(app: &Application, default_symbol: Option<String>) {
 
     b.append(&*datagrid.borrow());
 
+    // Requests that can't succeed are skipped while the system says we are
+    // offline, and everything is fetched again once we are back
+    let network_monitor = gio::NetworkMonitor::default();
+    stox_set_offline(!network_monitor.is_network_available());
+    network_monitor.connect_network_changed(clone!(@strong datagrid => move |_, available| {
+        let offline = !available;
+        if offline == stox_is_offline() {
+            return;
+        }
+
+        stox_set_offline(offline);
+
+        if available {
+            refresh_scheduler::refresh_all();
+            datagrid.borrow().refresh_if_outdated();
+        }
+    }));
+
     let previous_row: RefCell<Option<ListBoxRow>> = RefCell::new(None);
 
     sidebar.connect_row_selected(move |sidebar, row| {
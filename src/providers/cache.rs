@@ -0,0 +1,301 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use gtk4::glib;
+
+use serde::{Deserialize, Serialize};
+
+use crate::fs_persistence::{get_persistence_path, persistence_disabled};
+
+use super::transport::url_file_name;
+use super::LOG_DOMAIN;
+
+const CACHE_DIRNAME: &str = "cache";
+/// Older responses are of no use, not even as last known values
+const MAX_ENTRY_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+/// How many responses are kept on disk, the least recently fetched go first
+const MAX_ENTRIES: usize = 500;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CacheEntry {
+    /// Seconds since the Unix epoch
+    pub fetched_at: u64,
+    pub body: String,
+}
+
+impl CacheEntry {
+    pub fn age(&self) -> Duration {
+        let fetched_at = UNIX_EPOCH + Duration::from_secs(self.fetched_at);
+        SystemTime::now()
+            .duration_since(fetched_at)
+            .unwrap_or_default()
+    }
+}
+
+/// Response bodies keyed by URL, kept in memory and mirrored to disk so they
+/// survive restarts. The cache never decides what is fresh, callers compare
+/// an entry's age against the TTL of its endpoint. Both copies are kept to
+/// `MAX_ENTRIES` entries no older than `MAX_ENTRY_AGE`.
+pub struct ResponseCache {
+    memory: Mutex<HashMap<String, CacheEntry>>,
+    dir: Option<PathBuf>,
+}
+
+impl ResponseCache {
+    /// Without persistence the cache only lives in memory.
+    pub fn new() -> Self {
+        let dir = if persistence_disabled() {
+            None
+        } else {
+            get_persistence_path()
+                .ok()
+                .map(|path| path.join(CACHE_DIRNAME))
+        };
+
+        Self::with_dir(dir)
+    }
+
+    /// Mirrors the cache to `dir`, if any.
+    pub(super) fn with_dir(dir: Option<PathBuf>) -> Self {
+        if let Some(dir) = &dir {
+            prune(dir);
+        }
+
+        Self {
+            memory: Mutex::new(HashMap::new()),
+            dir,
+        }
+    }
+
+    pub fn get(&self, url: &str) -> Option<CacheEntry> {
+        let mut memory = self.memory.lock().unwrap();
+        if let Some(entry) = memory.get(url) {
+            return Some(entry.clone());
+        }
+
+        let path = self.dir.as_ref()?.join(url_file_name(url));
+        let entry: CacheEntry = serde_json::from_str(&fs::read_to_string(path).ok()?).ok()?;
+        remember(&mut memory, url, entry.clone());
+
+        Some(entry)
+    }
+
+    pub fn insert(&self, url: &str, body: &str) {
+        let fetched_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let entry = CacheEntry {
+            fetched_at,
+            body: body.to_owned(),
+        };
+
+        // The disk copy is only an optimization, so failing to write it is fine
+        if let Some(dir) = &self.dir {
+            // Only a new file can take the directory past the bound
+            let new = !dir.join(url_file_name(url)).exists();
+
+            match write_entry(dir, url, &entry) {
+                Ok(()) if new => prune(dir),
+                Ok(()) => {}
+                Err(err) => glib::g_warning!(LOG_DOMAIN, "Could not cache {}: {}", url, err),
+            }
+        }
+
+        remember(&mut self.memory.lock().unwrap(), url, entry);
+    }
+}
+
+impl Default for ResponseCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Keeps `entry` in `memory`, making room by dropping the entries that are too
+/// old and, past `MAX_ENTRIES`, the least recently fetched ones.
+fn remember(memory: &mut HashMap<String, CacheEntry>, url: &str, entry: CacheEntry) {
+    memory.remove(url);
+    memory.retain(|_, entry| entry.age() <= MAX_ENTRY_AGE);
+
+    if memory.len() >= MAX_ENTRIES {
+        let mut entries: Vec<(u64, String)> = memory
+            .iter()
+            .map(|(url, entry)| (entry.fetched_at, url.clone()))
+            .collect();
+        entries.sort_unstable();
+
+        for (_, url) in &entries[..=entries.len() - MAX_ENTRIES] {
+            memory.remove(url);
+        }
+    }
+
+    memory.insert(url.to_owned(), entry);
+}
+
+fn write_entry(dir: &Path, url: &str, entry: &CacheEntry) -> Result<(), anyhow::Error> {
+    let mut builder = DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    builder.mode(0o700);
+    builder.create(dir)?;
+
+    let mut options = OpenOptions::new();
+    options.write(true).truncate(true).create(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(dir.join(url_file_name(url)))?;
+
+    file.write_all(serde_json::to_string(entry)?.as_bytes())?;
+
+    Ok(())
+}
+
+/// Removes the entries that are too old and, past `MAX_ENTRIES`, the least
+/// recently written ones.
+fn prune(dir: &Path) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    let mut files: Vec<(SystemTime, PathBuf)> = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let modified = entry.metadata().ok()?.modified().ok()?;
+
+            Some((modified, entry.path()))
+        })
+        .filter(|(_, path)| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .collect();
+    // Newest first
+    files.sort_by_key(|(modified, _)| Reverse(*modified));
+
+    let now = SystemTime::now();
+    for (i, (modified, path)) in files.iter().enumerate() {
+        let age = now.duration_since(*modified).unwrap_or_default();
+
+        if i >= MAX_ENTRIES || age > MAX_ENTRY_AGE {
+            if let Err(err) = fs::remove_file(path) {
+                glib::g_warning!(LOG_DOMAIN, "Could not remove {}: {}", path.display(), err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use super::*;
+
+    use crate::providers::test_dir;
+
+    const URL: &str = "https://query1.finance.yahoo.com/v7/finance/quote?symbols=DEMO";
+
+    /// Writes an entry as if it had been fetched `age` ago.
+    fn write_aged_entry(dir: &Path, url: &str, age: Duration) {
+        let fetched_at = SystemTime::now() - age;
+        let entry = CacheEntry {
+            fetched_at: fetched_at.duration_since(UNIX_EPOCH).unwrap().as_secs(),
+            body: url.to_owned(),
+        };
+        write_entry(dir, url, &entry).unwrap();
+
+        File::options()
+            .write(true)
+            .open(dir.join(url_file_name(url)))
+            .unwrap()
+            .set_modified(fetched_at)
+            .unwrap();
+    }
+
+    fn entry_count(dir: &Path) -> usize {
+        fs::read_dir(dir).unwrap().count()
+    }
+
+    #[test]
+    fn entries_are_read_back_from_disk() {
+        let dir = test_dir("cache_from_disk");
+        ResponseCache::with_dir(Some(dir.clone())).insert(URL, "{}");
+
+        let entry = ResponseCache::with_dir(Some(dir)).get(URL).unwrap();
+
+        assert_eq!(entry.body, "{}");
+        assert!(entry.age() < Duration::from_secs(60));
+    }
+
+    #[test]
+    fn entries_keep_their_age() {
+        let dir = test_dir("cache_age");
+        write_aged_entry(&dir, URL, Duration::from_secs(60 * 60));
+
+        let entry = ResponseCache::with_dir(Some(dir)).get(URL).unwrap();
+
+        assert!(entry.age() >= Duration::from_secs(60 * 60));
+    }
+
+    #[test]
+    fn memory_only_cache() {
+        let cache = ResponseCache::with_dir(None);
+        assert!(cache.get(URL).is_none());
+
+        cache.insert(URL, "{}");
+
+        assert_eq!(cache.get(URL).unwrap().body, "{}");
+    }
+
+    #[test]
+    fn entries_expire_after_a_week() {
+        let dir = test_dir("cache_expiry");
+        let old_url = format!("{}&old", URL);
+        write_aged_entry(&dir, URL, Duration::from_secs(24 * 60 * 60));
+        write_aged_entry(&dir, &old_url, MAX_ENTRY_AGE + Duration::from_secs(60));
+
+        let cache = ResponseCache::with_dir(Some(dir.clone()));
+
+        assert_eq!(entry_count(&dir), 1);
+        assert!(cache.get(URL).is_some());
+        assert!(cache.get(&old_url).is_none());
+    }
+
+    #[test]
+    fn inserting_keeps_to_max_entries() {
+        let dir = test_dir("cache_bound");
+        let url = |i: usize| format!("{}&{}", URL, i);
+        let cache = ResponseCache::with_dir(Some(dir.clone()));
+
+        for i in 0..MAX_ENTRIES + 10 {
+            cache.insert(&url(i), "{}");
+        }
+
+        assert_eq!(entry_count(&dir), MAX_ENTRIES);
+        assert_eq!(cache.memory.lock().unwrap().len(), MAX_ENTRIES);
+        assert!(cache.get(&url(MAX_ENTRIES + 9)).is_some());
+    }
+
+    #[test]
+    fn pruning_keeps_the_newest_entries() {
+        let dir = test_dir("cache_pruning");
+        let url = |i: usize| format!("{}&{}", URL, i);
+        for i in 0..MAX_ENTRIES + 10 {
+            write_aged_entry(&dir, &url(i), Duration::from_secs(i as u64 * 60));
+        }
+
+        let cache = ResponseCache::with_dir(Some(dir.clone()));
+
+        assert_eq!(entry_count(&dir), MAX_ENTRIES);
+        assert!(cache.get(&url(MAX_ENTRIES - 1)).is_some());
+        assert!(cache.get(&url(MAX_ENTRIES)).is_none());
+    }
+}
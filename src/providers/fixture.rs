@@ -0,0 +1,214 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use yahoo_finance_api::{YQuoteItem, YResponse, YSearchResult, YSearchResultOpt};
+
+use crate::data_helper::{ChartInfo, ExtendedInfo, MainInfo, StatsInfo};
+use crate::error::{Result, StoxError};
+
+use super::yahoo::{parse_chart_info, parse_extended_info, parse_main_info, parse_stats_info};
+use super::QuoteProvider;
+
+/// Serves recorded Yahoo responses from a directory instead of the network.
+///
+/// The directory is laid out by endpoint:
+///
+/// ```text
+/// search/<query>.json                    v1/finance/search
+/// chart/<SYMBOL>.json                    v8/finance/chart
+/// chart/<SYMBOL>-<range>-<interval>.json v8/finance/chart (optional)
+/// options/<SYMBOL>.json                  v7/finance/options
+/// quoteSummary/<SYMBOL>.json             v11/finance/quoteSummary
+/// ```
+///
+/// Range specific charts are preferred when they exist, otherwise every
+/// range is served from `chart/<SYMBOL>.json`.
+pub struct FixtureProvider {
+    dir: PathBuf,
+}
+
+impl FixtureProvider {
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Names are symbols and search queries as typed, so those that could
+    /// lead out of the directory are taken for unknown symbols.
+    fn path(&self, endpoint: &str, name: &str) -> Result<PathBuf> {
+        if name.contains(['/', '\\']) || name.contains("..") {
+            return Err(StoxError::UnknownSymbol);
+        }
+
+        Ok(self.dir.join(endpoint).join(name.to_owned() + ".json"))
+    }
+
+    fn read(&self, endpoint: &str, name: &str) -> Result<serde_json::Value> {
+        let path = self.path(endpoint, name)?;
+        // A symbol without fixtures is as good as one that doesn't exist
+        let data = fs::read_to_string(path).map_err(|_| StoxError::UnknownSymbol)?;
+
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    fn has(&self, endpoint: &str, name: &str) -> bool {
+        self.path(endpoint, name).is_ok_and(|path| path.is_file())
+    }
+
+    fn chart(&self, symbol: &str, interval: &str, range: &str) -> Result<serde_json::Value> {
+        let name = format!("{}-{}-{}", symbol, range, interval);
+
+        if self.has("chart", &name) {
+            self.read("chart", &name)
+        } else {
+            self.read("chart", symbol)
+        }
+    }
+}
+
+impl QuoteProvider for FixtureProvider {
+    fn search(&self, query: &str) -> Result<Vec<YQuoteItem>> {
+        let result = YSearchResultOpt::from_json(self.read("search", query)?)?;
+
+        Ok(YSearchResult::from_opt(&result).quotes)
+    }
+
+    fn main_info(&self, symbol: &str) -> Result<MainInfo> {
+        parse_main_info(
+            symbol,
+            YResponse::from_json(self.chart(symbol, "1h", "1mo")?)?,
+            &self.search(symbol)?,
+        )
+    }
+
+    fn extended_info(&self, symbol: &str) -> Result<ExtendedInfo> {
+        parse_extended_info(&self.read("options", symbol)?)
+    }
+
+    fn stats_info(&self, symbol: &str) -> Result<StatsInfo> {
+        parse_stats_info(&self.read("quoteSummary", symbol)?)
+    }
+
+    fn quote_history(&self, symbol: &str, interval: &str, range: &str) -> Result<ChartInfo> {
+        parse_chart_info(self.chart(symbol, interval, range)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rust_decimal::prelude::*;
+
+    use crate::providers::yahoo::parse_quotes_batch;
+
+    fn provider() -> FixtureProvider {
+        FixtureProvider::new(concat!(env!("CARGO_MANIFEST_DIR"), "/data/fixtures"))
+    }
+
+    #[test]
+    fn search() {
+        let quotes = provider().search("DEMO").unwrap();
+
+        assert_eq!(quotes.len(), 1);
+        assert_eq!(quotes[0].symbol, "DEMO");
+        assert_eq!(quotes[0].long_name, "Demo Corporation");
+        assert_eq!(quotes[0].exchange, "NMS");
+    }
+
+    #[test]
+    fn main_info() {
+        let main_info = provider().main_info("DEMO").unwrap();
+
+        assert_eq!(main_info.last_quote, Decimal::from_f64(152.59).unwrap());
+        assert_eq!(main_info.name, "Demo Corporation");
+        assert_eq!(main_info.currency, "USD");
+        assert!(!main_info.bankruptcy);
+    }
+
+    #[test]
+    fn extended_info() {
+        let extended_info = provider().extended_info("DEMO").unwrap();
+
+        assert_eq!(extended_info.exchange_name, "NasdaqGS");
+        assert_eq!(extended_info.market_change, 2.48);
+        assert_eq!(extended_info.market_change_percent, 1.652122);
+    }
+
+    #[test]
+    fn stats_info() {
+        let stats_info = provider().stats_info("DEMO").unwrap();
+
+        assert_eq!(stats_info.open, Some(150.2));
+        assert_eq!(stats_info.high, Some(152.8));
+        assert_eq!(stats_info.low, Some(149.9));
+        assert_eq!(stats_info.volume, Some(39788812));
+        assert_eq!(stats_info.pe_ratio, Some(25.91));
+        assert_eq!(stats_info.market_cap, Some(2412345678901));
+        assert_eq!(stats_info.dividend_yield, Some(0.0061));
+        assert_eq!(stats_info.beta, Some(1.28));
+        assert_eq!(stats_info.eps, Some(5.89));
+    }
+
+    #[test]
+    fn chart_info() {
+        let chart_info = provider().quote_history("DEMO", "1h", "1d").unwrap();
+
+        assert_eq!(chart_info.quotes.len(), 7);
+        assert_eq!(chart_info.quotes[0].timestamp, 1678113000);
+        assert_eq!(chart_info.quotes[0].open, 150.2);
+        assert_eq!(chart_info.quotes[0].volume, 8102345);
+        assert_eq!(chart_info.quotes[6].adjclose, 152.59);
+        assert_eq!(chart_info.currency, "USD");
+        assert_eq!(chart_info.timezone, "America/New_York");
+        assert_eq!(chart_info.utc_offset, -18000);
+        assert_eq!(chart_info.previous_close, Some(150.11));
+        assert_eq!(chart_info.trading_periods.len(), 1);
+    }
+
+    #[test]
+    fn quotes_batch() {
+        // The options response carries the same quote as the v7 quote
+        // endpoint, which has no fixtures of its own
+        let options = provider().read("options", "DEMO").unwrap();
+        let data = serde_json::json!({
+            "quoteResponse": {
+                "result": [options["optionChain"]["result"][0]["quote"]],
+            },
+        });
+
+        let quotes = parse_quotes_batch(&data).unwrap();
+        let (main_info, extended_info) = &quotes["DEMO"];
+
+        assert_eq!(main_info.last_quote, Decimal::from_f64(152.59).unwrap());
+        assert_eq!(main_info.name, "Demo Corporation");
+        assert_eq!(extended_info.exchange_name, "NasdaqGS");
+    }
+
+    #[test]
+    fn unknown_symbol() {
+        assert_eq!(
+            provider().stats_info("NOPE").err(),
+            Some(StoxError::UnknownSymbol)
+        );
+    }
+
+    #[test]
+    fn names_stay_inside_the_directory() {
+        let provider = provider();
+
+        assert_eq!(
+            provider.extended_info("../options/DEMO").err(),
+            Some(StoxError::UnknownSymbol)
+        );
+        assert_eq!(
+            provider.search("..\\search\\DEMO").err(),
+            Some(StoxError::UnknownSymbol)
+        );
+        assert_eq!(
+            provider.search("../../etc/passwd").err(),
+            Some(StoxError::UnknownSymbol)
+        );
+    }
+}
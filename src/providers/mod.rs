@@ -0,0 +1,101 @@
+mod cache;
+mod fixture;
+mod transport;
+mod yahoo;
+
+use std::collections::HashMap;
+use std::env;
+
+use gtk4::gio::{self, prelude::*};
+
+use yahoo_finance_api::YQuoteItem;
+
+use crate::data_helper::{ChartInfo, ExtendedInfo, MainInfo, StatsInfo};
+use crate::error::{Result, StoxError};
+use crate::LOG_DOMAIN;
+
+pub use fixture::FixtureProvider;
+pub use transport::{is_offline, set_offline};
+pub use yahoo::{YahooProvider, DEFAULT_BASE_URL};
+
+/// A source of market data. Everything in `data_helper` goes through one of
+/// these, so the widgets never need to know where the data comes from.
+pub trait QuoteProvider: Send + Sync {
+    fn search(&self, query: &str) -> Result<Vec<YQuoteItem>>;
+
+    fn main_info(&self, symbol: &str) -> Result<MainInfo>;
+
+    fn extended_info(&self, symbol: &str) -> Result<ExtendedInfo>;
+
+    fn stats_info(&self, symbol: &str) -> Result<StatsInfo>;
+
+    /// The bars of a chart range, including pre and post-market trading
+    /// where the interval is short enough to show it.
+    fn quote_history(&self, symbol: &str, interval: &str, range: &str) -> Result<ChartInfo>;
+
+    /// The main and extended info of many symbols at once, keyed by symbol.
+    /// Unknown symbols are left out. Providers that can't batch requests
+    /// fall back to fetching one symbol at a time.
+    fn quotes_batch(&self, symbols: &[&str]) -> Result<HashMap<String, (MainInfo, ExtendedInfo)>> {
+        let mut quotes = HashMap::new();
+
+        for symbol in symbols {
+            let info = self
+                .main_info(symbol)
+                .and_then(|main_info| Ok((main_info, self.extended_info(symbol)?)));
+
+            match info {
+                Ok(info) => {
+                    quotes.insert(symbol.to_string(), info);
+                }
+                Err(StoxError::UnknownSymbol) => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(quotes)
+    }
+}
+
+/// Picks the provider for this run. Setting `STOX_FIXTURE_DIR` serves
+/// recorded responses from that directory so stox can run without network
+/// access, otherwise the data comes from Yahoo (which can itself be recorded
+/// or replayed, see `Transport`).
+pub fn from_env() -> Box<dyn QuoteProvider> {
+    match env::var("STOX_FIXTURE_DIR") {
+        Ok(dir) if !dir.is_empty() => Box::new(FixtureProvider::new(dir)),
+        _ => Box::new(YahooProvider::new(&base_url())),
+    }
+}
+
+/// `STOX_API_BASE_URL` overrides the `api-base-url` setting. The schema is
+/// looked up first so this still works when it isn't installed.
+fn base_url() -> String {
+    if let Ok(url) = env::var("STOX_API_BASE_URL") {
+        if !url.is_empty() {
+            return url;
+        }
+    }
+
+    let schema =
+        gio::SettingsSchemaSource::default().and_then(|source| source.lookup(crate::APP_ID, true));
+    if schema.is_some() {
+        let url = gio::Settings::new(crate::APP_ID).string("api-base-url");
+        if !url.is_empty() {
+            return url.to_string();
+        }
+    }
+
+    DEFAULT_BASE_URL.to_owned()
+}
+
+/// An empty directory for a test to write to, named after the test.
+#[cfg(test)]
+fn test_dir(name: &str) -> std::path::PathBuf {
+    let dir = env::temp_dir().join(format!("stox-{}-{}", name, std::process::id()));
+    // Left over from an earlier run if it exists
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    dir
+}
@@ -0,0 +1,361 @@
+use std::env;
+use std::fs::{self, DirBuilder};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use chrono::prelude::*;
+
+use gtk4::glib;
+
+use reqwest::blocking::Client;
+use reqwest::StatusCode;
+
+use sha2::{Digest, Sha256};
+
+use crate::error::{Result, StoxError};
+
+use super::cache::{CacheEntry, ResponseCache};
+use super::LOG_DOMAIN;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// Whether the system says there is no network.
+pub fn is_offline() -> bool {
+    OFFLINE.load(Ordering::Relaxed)
+}
+
+pub fn set_offline(offline: bool) {
+    OFFLINE.store(offline, Ordering::Relaxed);
+}
+
+/// A response body, and when it was fetched if it is the last known response
+/// served while offline.
+pub struct Response {
+    pub body: String,
+    pub as_of: Option<DateTime<Utc>>,
+}
+
+impl Response {
+    fn live(body: String) -> Self {
+        Self { body, as_of: None }
+    }
+
+    fn stale(entry: CacheEntry) -> Self {
+        Self {
+            as_of: Utc.timestamp_opt(entry.fetched_at as i64, 0).single(),
+            body: entry.body,
+        }
+    }
+}
+
+/// How HTTP requests are answered. Live requests go through the response
+/// cache. Recording saves every response body under a file named after a
+/// hash of its URL, and replaying serves those files back without touching
+/// the network.
+pub enum Transport {
+    Live(Client, ResponseCache),
+    Record(Client, PathBuf),
+    Replay(PathBuf),
+}
+
+impl Transport {
+    /// `STOX_REPLAY_DIR` takes precedence over `STOX_RECORD_DIR`, both default
+    /// to fetching live.
+    pub fn from_env() -> Self {
+        if let Some(dir) = env_dir("STOX_REPLAY_DIR") {
+            return Self::Replay(dir);
+        }
+
+        if let Some(dir) = env_dir("STOX_RECORD_DIR") {
+            return Self::Record(client(), dir);
+        }
+
+        Self::Live(client(), ResponseCache::new())
+    }
+
+    /// A cached response younger than `ttl` is served without a request. When
+    /// the network is down, the last known response is served however old it
+    /// is. While the system says we are offline, a request is only attempted
+    /// when nothing was cached.
+    pub fn get(&self, url: &str, ttl: Duration) -> Result<Response> {
+        match self {
+            Self::Live(client, cache) => get_live(client, cache, url, ttl, is_offline()),
+            Self::Record(client, dir) => {
+                let body = fetch(client, url)?;
+
+                // Losing a recording shouldn't stop the app from working
+                if let Err(err) = record(dir, url, &body) {
+                    glib::g_warning!(LOG_DOMAIN, "Could not record {}: {}", url, err);
+                }
+
+                Ok(Response::live(body))
+            }
+            // Like with fixtures, a symbol that wasn't recorded is as good as
+            // one that doesn't exist
+            Self::Replay(dir) => fs::read_to_string(recording_path(dir, url))
+                .map(Response::live)
+                .map_err(|_| StoxError::UnknownSymbol),
+        }
+    }
+
+    pub fn get_json(
+        &self,
+        url: &str,
+        ttl: Duration,
+    ) -> Result<(serde_json::Value, Option<DateTime<Utc>>)> {
+        let response = self.get(url, ttl)?;
+
+        Ok((serde_json::from_str(&response.body)?, response.as_of))
+    }
+}
+
+fn env_dir(name: &str) -> Option<PathBuf> {
+    match env::var(name) {
+        Ok(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => None,
+    }
+}
+
+fn client() -> Client {
+    Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .unwrap_or_default()
+}
+
+/// Answers a live request, see `Transport::get`. `offline` is whether the
+/// system says there is no network.
+fn get_live(
+    client: &Client,
+    cache: &ResponseCache,
+    url: &str,
+    ttl: Duration,
+    offline: bool,
+) -> Result<Response> {
+    let cached = cache.get(url);
+    if let Some(entry) = cached.clone() {
+        if entry.age() < ttl {
+            return Ok(Response::live(entry.body));
+        }
+
+        if offline {
+            return Ok(Response::stale(entry));
+        }
+    }
+
+    match fetch(client, url) {
+        Ok(body) => {
+            cache.insert(url, &body);
+            Ok(Response::live(body))
+        }
+        Err(StoxError::Network) if cached.is_some() => Ok(Response::stale(cached.unwrap())),
+        Err(err) => Err(err),
+    }
+}
+
+fn fetch(client: &Client, url: &str) -> Result<String> {
+    let response = client.get(url).send()?;
+
+    match status_error(response.status()) {
+        Some(err) => Err(err),
+        None => Ok(response.text()?),
+    }
+}
+
+/// Why a request answered with `status` failed, if it did. Yahoo answers
+/// unknown symbols with a 404.
+fn status_error(status: StatusCode) -> Option<StoxError> {
+    match status {
+        StatusCode::NOT_FOUND => Some(StoxError::UnknownSymbol),
+        StatusCode::TOO_MANY_REQUESTS => Some(StoxError::RateLimited),
+        status if !status.is_success() => Some(StoxError::Network),
+        _ => None,
+    }
+}
+
+fn record(dir: &Path, url: &str, body: &str) -> std::io::Result<()> {
+    DirBuilder::new().recursive(true).create(dir)?;
+    fs::write(recording_path(dir, url), body)?;
+
+    Ok(())
+}
+
+fn recording_path(dir: &Path, url: &str) -> PathBuf {
+    dir.join(url_file_name(url))
+}
+
+/// The URL is hashed so that every file name is as long, however many
+/// symbols a batch URL asks for.
+pub fn url_file_name(url: &str) -> String {
+    format!("{:x}.json", Sha256::digest(url))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    use super::*;
+
+    use crate::providers::test_dir;
+
+    /// Answers a single request with `body` and returns the URL to send it
+    /// to.
+    fn serve_once(body: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/v7/finance/quote", listener.local_addr().unwrap());
+
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+
+            // The request has no body, so it ends with the headers
+            let mut request = vec![];
+            let mut buffer = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let read = stream.read(&mut buffer).unwrap();
+                if read == 0 {
+                    return;
+                }
+                request.extend_from_slice(&buffer[..read]);
+            }
+
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+        });
+
+        url
+    }
+
+    /// A URL that is never answered, so every request to it times out. The
+    /// port stays taken for as long as the listener is kept.
+    fn unreachable_url() -> (TcpListener, String) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/v7/finance/quote", listener.local_addr().unwrap());
+
+        (listener, url)
+    }
+
+    /// Gives up on unanswered requests quickly.
+    fn test_client() -> Client {
+        Client::builder()
+            .timeout(Duration::from_millis(200))
+            .build()
+            .unwrap()
+    }
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn fresh_responses_are_served_from_the_cache() {
+        let (_listener, url) = unreachable_url();
+        let cache = ResponseCache::with_dir(None);
+        cache.insert(&url, "cached");
+
+        let response = get_live(&test_client(), &cache, &url, TTL, false).unwrap();
+
+        assert_eq!(response.body, "cached");
+        assert_eq!(response.as_of, None);
+    }
+
+    #[test]
+    fn expired_responses_are_fetched_again() {
+        let url = serve_once("fetched");
+        let cache = ResponseCache::with_dir(None);
+        cache.insert(&url, "cached");
+
+        let response = get_live(&test_client(), &cache, &url, Duration::ZERO, false).unwrap();
+
+        assert_eq!(response.body, "fetched");
+        assert_eq!(response.as_of, None);
+        assert_eq!(cache.get(&url).unwrap().body, "fetched");
+    }
+
+    #[test]
+    fn expired_responses_are_served_stale_while_offline() {
+        let (_listener, url) = unreachable_url();
+        let cache = ResponseCache::with_dir(None);
+        cache.insert(&url, "cached");
+
+        let response = get_live(&test_client(), &cache, &url, Duration::ZERO, true).unwrap();
+
+        assert_eq!(response.body, "cached");
+        assert!(response.as_of.is_some());
+    }
+
+    #[test]
+    fn expired_responses_are_served_stale_when_the_network_fails() {
+        let (_listener, url) = unreachable_url();
+        let cache = ResponseCache::with_dir(None);
+        cache.insert(&url, "cached");
+
+        let response = get_live(&test_client(), &cache, &url, Duration::ZERO, false).unwrap();
+
+        assert_eq!(response.body, "cached");
+        assert!(response.as_of.is_some());
+    }
+
+    #[test]
+    fn failed_requests_without_a_cached_response() {
+        let (_listener, url) = unreachable_url();
+        let cache = ResponseCache::with_dir(None);
+
+        assert_eq!(
+            get_live(&test_client(), &cache, &url, TTL, true).err(),
+            Some(StoxError::Network)
+        );
+    }
+
+    #[test]
+    fn recordings_are_replayed() {
+        let dir = test_dir("transport_recording");
+        let url = serve_once("recorded");
+
+        let recorded = Transport::Record(test_client(), dir.clone())
+            .get(&url, TTL)
+            .unwrap();
+        let replayed = Transport::Replay(dir.clone()).get(&url, TTL).unwrap();
+
+        assert_eq!(recorded.body, "recorded");
+        assert_eq!(replayed.body, "recorded");
+        assert!(recording_path(&dir, &url).exists());
+    }
+
+    #[test]
+    fn missing_recordings_are_unknown_symbols() {
+        let replay = Transport::Replay(test_dir("transport_replay"));
+
+        assert_eq!(
+            replay.get(&unreachable_url().1, TTL).err(),
+            Some(StoxError::UnknownSymbol)
+        );
+    }
+
+    #[test]
+    fn statuses_map_to_errors() {
+        assert_eq!(status_error(StatusCode::OK), None);
+        assert_eq!(
+            status_error(StatusCode::NOT_FOUND),
+            Some(StoxError::UnknownSymbol)
+        );
+        assert_eq!(
+            status_error(StatusCode::TOO_MANY_REQUESTS),
+            Some(StoxError::RateLimited)
+        );
+        assert_eq!(
+            status_error(StatusCode::INTERNAL_SERVER_ERROR),
+            Some(StoxError::Network)
+        );
+        assert_eq!(
+            status_error(StatusCode::UNAUTHORIZED),
+            Some(StoxError::Network)
+        );
+    }
+}
@@ -0,0 +1,507 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use chrono::prelude::*;
+
+use gtk4::glib;
+
+use rust_decimal::prelude::*;
+
+use yahoo_finance_api::{YQuoteItem, YResponse, YSearchResult, YSearchResultOpt};
+
+use crate::data_helper::{
+    ChartInfo, ExtendedInfo, MainInfo, MarketSession, QuoteGaps, StatsInfo, TradingPeriod,
+};
+use crate::error::{Result, StoxError};
+
+use super::transport::Transport;
+use super::{QuoteProvider, LOG_DOMAIN};
+
+pub const DEFAULT_BASE_URL: &str = "https://query1.finance.yahoo.com";
+
+const CHART_PATH: &str = "/v8/finance/chart/";
+const SEARCH_PATH: &str = "/v1/finance/search";
+const OPTIONS_PATH: &str = "/v7/finance/options/";
+const QUOTE_SUMMARY_PATH: &str = "/v11/finance/quoteSummary/";
+const QUOTE_PATH: &str = "/v7/finance/quote";
+
+// How long a cached response is served before asking Yahoo again. Quotes
+// move constantly, names and exchanges hardly ever and stats once a day.
+// The batch quotes stay just under the sidebar's refresh interval.
+const QUOTES_TTL: Duration = Duration::from_secs(4);
+const CHART_TTL: Duration = Duration::from_secs(60);
+const SEARCH_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+const STATS_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+macro_rules! stat_raw {
+    ($stat:expr) => {
+        $stat["raw"].as_f64()
+    };
+    ($stat:expr, u64) => {
+        $stat["raw"].as_f64().map(|raw| raw as u64)
+    };
+}
+
+pub struct YahooProvider {
+    base_url: String,
+    transport: Transport,
+}
+
+impl YahooProvider {
+    /// Every request is made against `base_url`, which lets a mock server or
+    /// a caching proxy stand in for Yahoo.
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            transport: Transport::from_env(),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        self.base_url.clone() + path
+    }
+
+    /// Also returns when the response was fetched, if it was served stale.
+    fn chart(
+        &self,
+        symbol: &str,
+        interval: &str,
+        range: &str,
+    ) -> Result<(YResponse, Option<DateTime<Utc>>)> {
+        let (data, as_of) = self.chart_json(symbol, interval, range, false)?;
+
+        Ok((YResponse::from_json(data)?, as_of))
+    }
+
+    /// Yahoo leaves pre and post-market bars out unless `include_pre_post`
+    /// asks for them, which only makes a difference for intraday intervals.
+    fn chart_json(
+        &self,
+        symbol: &str,
+        interval: &str,
+        range: &str,
+        include_pre_post: bool,
+    ) -> Result<(serde_json::Value, Option<DateTime<Utc>>)> {
+        let symbol = urlencoding::encode(symbol);
+        let url = format!(
+            "{}{}?symbol={}&interval={}&range={}&includePrePost={}&events=div|split",
+            self.url(CHART_PATH),
+            symbol,
+            symbol,
+            interval,
+            range,
+            include_pre_post
+        );
+
+        self.transport.get_json(&url, CHART_TTL)
+    }
+}
+
+impl Default for YahooProvider {
+    fn default() -> Self {
+        Self::new(DEFAULT_BASE_URL)
+    }
+}
+
+impl QuoteProvider for YahooProvider {
+    fn search(&self, query: &str) -> Result<Vec<YQuoteItem>> {
+        let url = format!("{}?q={}", self.url(SEARCH_PATH), urlencoding::encode(query));
+        let (data, _) = self.transport.get_json(&url, SEARCH_TTL)?;
+        let result = YSearchResultOpt::from_json(data)?;
+
+        Ok(YSearchResult::from_opt(&result).quotes)
+    }
+
+    fn main_info(&self, symbol: &str) -> Result<MainInfo> {
+        let (latest_quotes, as_of) = self.chart(symbol, "1h", "1mo")?;
+        let quote_items = self.search(symbol)?;
+
+        let mut main_info = parse_main_info(symbol, latest_quotes, &quote_items)?;
+        main_info.as_of = as_of;
+
+        Ok(main_info)
+    }
+
+    fn extended_info(&self, symbol: &str) -> Result<ExtendedInfo> {
+        let url = format!("{}{}", self.url(OPTIONS_PATH), urlencoding::encode(symbol));
+
+        let (data, as_of) = self.transport.get_json(&url, CHART_TTL)?;
+
+        let mut extended_info = parse_extended_info(&data)?;
+        extended_info.as_of = as_of;
+
+        Ok(extended_info)
+    }
+
+    fn stats_info(&self, symbol: &str) -> Result<StatsInfo> {
+        let url = format!(
+            "{}{}{}",
+            self.url(QUOTE_SUMMARY_PATH),
+            urlencoding::encode(symbol),
+            "?modules=defaultKeyStatistics,summaryDetail"
+        );
+
+        let (data, as_of) = self.transport.get_json(&url, STATS_TTL)?;
+
+        let mut stats_info = parse_stats_info(&data)?;
+        stats_info.as_of = as_of;
+
+        Ok(stats_info)
+    }
+
+    fn quote_history(&self, symbol: &str, interval: &str, range: &str) -> Result<ChartInfo> {
+        let (data, as_of) = self.chart_json(symbol, interval, range, true)?;
+
+        let mut chart_info = parse_chart_info(data)?;
+        chart_info.as_of = as_of;
+
+        Ok(chart_info)
+    }
+
+    fn quotes_batch(&self, symbols: &[&str]) -> Result<HashMap<String, (MainInfo, ExtendedInfo)>> {
+        let symbols: Vec<String> = symbols
+            .iter()
+            .map(|symbol| urlencoding::encode(symbol).into_owned())
+            .collect();
+        let url = format!("{}?symbols={}", self.url(QUOTE_PATH), symbols.join(","));
+
+        let (data, as_of) = self.transport.get_json(&url, QUOTES_TTL)?;
+
+        let mut quotes = parse_quotes_batch(&data)?;
+        for (main_info, extended_info) in quotes.values_mut() {
+            main_info.as_of = as_of;
+            extended_info.as_of = as_of;
+        }
+
+        Ok(quotes)
+    }
+}
+
+// Typically, if a company is undergoing bankruptcy they will
+// add "Q" to the end of their stock symbol in 5-chars length
+fn bankruptcy(symbol: &str) -> bool {
+    symbol.ends_with('Q') && symbol.len() == 5
+}
+
+/// Builds the main info out of a v8 chart response and the v1 search results
+/// for the symbol.
+pub(super) fn parse_main_info(
+    symbol: &str,
+    latest_quotes: YResponse,
+    quote_items: &[YQuoteItem],
+) -> Result<MainInfo> {
+    let last_quote = latest_quotes.last_quote()?.close;
+    let last_quote = Decimal::from_f64(last_quote).ok_or_else(|| StoxError::parse("close"))?;
+
+    let quote_item = quote_items.first().ok_or(StoxError::UnknownSymbol)?;
+    let mut name = &quote_item.long_name;
+    if name.is_empty() {
+        name = &quote_item.short_name;
+    }
+
+    let meta = &latest_quotes
+        .chart
+        .result
+        .first()
+        .ok_or(StoxError::NoData)?
+        .meta;
+    let currency = meta.currency.to_uppercase();
+    let instrument_type = meta.instrument_type.to_string();
+
+    Ok(MainInfo {
+        last_quote,
+        name: name.to_string(),
+        instrument_type,
+        currency,
+        bankruptcy: bankruptcy(symbol),
+        as_of: None,
+    })
+}
+
+/// Builds the chart info out of a v8 chart response. With `includePrePost`
+/// the trading periods come split up by session, a shape `YResponse` can't
+/// read, so they are taken out and read here.
+pub(super) fn parse_chart_info(mut data: serde_json::Value) -> Result<ChartInfo> {
+    let trading_periods = data
+        .pointer_mut("/chart/result/0/meta")
+        .and_then(|meta| meta.as_object_mut()?.remove("tradingPeriods"))
+        .map(|periods| parse_trading_periods(&periods))
+        .unwrap_or_default();
+    let gaps = data
+        .pointer("/chart/result/0/indicators")
+        .map(parse_quote_gaps)
+        .unwrap_or_default();
+
+    let response = YResponse::from_json(data)?;
+    let quotes = response.quotes()?;
+
+    if quotes.is_empty() {
+        return Err(StoxError::NoData);
+    }
+
+    let meta = &response.chart.result.first().ok_or(StoxError::NoData)?.meta;
+
+    Ok(ChartInfo {
+        quotes,
+        currency: meta.currency.to_uppercase(),
+        timezone: meta.exchange_timezone_name.clone(),
+        utc_offset: meta.gmtoffset,
+        previous_close: meta.previous_close,
+        trading_periods,
+        gaps,
+        as_of: None,
+    })
+}
+
+/// Which values Yahoo sent as null, for every bar `YResponse` keeps: those
+/// with a close. Intraday bars have no adjusted close at all.
+fn parse_quote_gaps(indicators: &serde_json::Value) -> Vec<QuoteGaps> {
+    let quote = &indicators["quote"][0];
+    let adjclose = &indicators["adjclose"][0]["adjclose"];
+
+    let closes = quote["close"].as_array().into_iter().flatten();
+    closes
+        .enumerate()
+        .filter(|(_, close)| !close.is_null())
+        .map(|(i, _)| QuoteGaps {
+            open: quote["open"][i].is_null(),
+            high: quote["high"][i].is_null(),
+            low: quote["low"][i].is_null(),
+            volume: quote["volume"][i].is_null(),
+            adjclose: adjclose[i].is_null(),
+        })
+        .collect()
+}
+
+/// The trading periods are a list of days, each a list of periods. Without
+/// pre and post-market data there is a single such list for the regular
+/// session.
+fn parse_trading_periods(periods: &serde_json::Value) -> Vec<TradingPeriod> {
+    let sessions = if periods.is_array() {
+        vec![(MarketSession::Regular, periods)]
+    } else {
+        vec![
+            (MarketSession::Pre, &periods["pre"]),
+            (MarketSession::Regular, &periods["regular"]),
+            (MarketSession::Post, &periods["post"]),
+        ]
+    };
+
+    let mut trading_periods = vec![];
+    for (session, days) in sessions {
+        let days = days.as_array().into_iter().flatten();
+
+        for period in days.flat_map(|day| day.as_array().into_iter().flatten()) {
+            if let (Some(start), Some(end)) = (period["start"].as_u64(), period["end"].as_u64()) {
+                trading_periods.push(TradingPeriod {
+                    session,
+                    start,
+                    end,
+                });
+            }
+        }
+    }
+
+    trading_periods
+}
+
+/// Reads the extended info out of a v7 options response.
+pub(super) fn parse_extended_info(data: &serde_json::Value) -> Result<ExtendedInfo> {
+    let quote = &data["optionChain"]["result"][0]["quote"];
+    if quote.is_null() {
+        // Yahoo answers unknown symbols with an empty result
+        return Err(StoxError::UnknownSymbol);
+    }
+
+    extended_info_from_quote(quote)
+}
+
+/// The options response embeds the same quote object as the v7 quote
+/// endpoint, so both are read the same way.
+fn extended_info_from_quote(quote: &serde_json::Value) -> Result<ExtendedInfo> {
+    let exchange_name = quote["fullExchangeName"]
+        .as_str()
+        .ok_or_else(|| StoxError::parse("fullExchangeName"))?
+        .to_owned();
+    let market_change = quote["regularMarketChange"]
+        .as_f64()
+        .ok_or_else(|| StoxError::parse("regularMarketChange"))?;
+    let market_change_percent = quote["regularMarketChangePercent"]
+        .as_f64()
+        .ok_or_else(|| StoxError::parse("regularMarketChangePercent"))?;
+
+    Ok(ExtendedInfo {
+        exchange_name,
+        market_change,
+        market_change_percent,
+        as_of: None,
+    })
+}
+
+/// Reads the main and extended info of every symbol out of a v7 quote
+/// response. Symbols Yahoo doesn't know are left out, and so are those it
+/// sends without a price, e.g. halted or delisted ones, so that they are
+/// reported as not found without failing the others.
+pub(super) fn parse_quotes_batch(
+    data: &serde_json::Value,
+) -> Result<HashMap<String, (MainInfo, ExtendedInfo)>> {
+    let results = data["quoteResponse"]["result"]
+        .as_array()
+        .ok_or_else(|| StoxError::parse("quoteResponse"))?;
+
+    let mut quotes = HashMap::new();
+    for quote in results {
+        match parse_batch_quote(quote) {
+            Ok((symbol, info)) => {
+                quotes.insert(symbol, info);
+            }
+            Err(err) => glib::g_warning!(
+                LOG_DOMAIN,
+                "Skipping the quote of {}: {}",
+                quote["symbol"].as_str().unwrap_or("a symbol"),
+                err
+            ),
+        }
+    }
+
+    Ok(quotes)
+}
+
+fn parse_batch_quote(quote: &serde_json::Value) -> Result<(String, (MainInfo, ExtendedInfo))> {
+    let symbol = quote["symbol"]
+        .as_str()
+        .ok_or_else(|| StoxError::parse("symbol"))?;
+
+    let last_quote = quote["regularMarketPrice"]
+        .as_f64()
+        .and_then(Decimal::from_f64)
+        .ok_or_else(|| StoxError::parse("regularMarketPrice"))?;
+
+    let name = quote["longName"]
+        .as_str()
+        .or_else(|| quote["shortName"].as_str())
+        .unwrap_or(symbol);
+
+    let main_info = MainInfo {
+        last_quote,
+        name: name.to_owned(),
+        instrument_type: quote["quoteType"].as_str().unwrap_or_default().to_owned(),
+        currency: quote["currency"]
+            .as_str()
+            .unwrap_or_default()
+            .to_uppercase(),
+        bankruptcy: bankruptcy(symbol),
+        as_of: None,
+    };
+
+    Ok((
+        symbol.to_owned(),
+        (main_info, extended_info_from_quote(quote)?),
+    ))
+}
+
+/// Reads the stats out of a v11 quoteSummary response with the
+/// `defaultKeyStatistics` and `summaryDetail` modules.
+pub(super) fn parse_stats_info(data: &serde_json::Value) -> Result<StatsInfo> {
+    let result = &data["quoteSummary"]["result"][0];
+    if result.is_null() {
+        return Err(StoxError::UnknownSymbol);
+    }
+    let summary_detail = &result["summaryDetail"];
+
+    let open = stat_raw!(summary_detail["open"]);
+    let high = stat_raw!(summary_detail["dayHigh"]);
+    let low = stat_raw!(summary_detail["dayLow"]);
+    let volume = stat_raw!(summary_detail["volume"], u64);
+    let pe_ratio = stat_raw!(summary_detail["trailingPE"]);
+    let market_cap = stat_raw!(summary_detail["marketCap"], u64);
+    let dividend_yield = stat_raw!(summary_detail["dividendYield"]);
+    let beta = stat_raw!(summary_detail["beta"]);
+    let eps = stat_raw!(result["defaultKeyStatistics"]["trailingEps"]);
+
+    Ok(StatsInfo {
+        open,
+        high,
+        low,
+        volume,
+        pe_ratio,
+        market_cap,
+        dividend_yield,
+        beta,
+        eps,
+        as_of: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batch_skips_quotes_without_a_price() {
+        let data = serde_json::json!({
+            "quoteResponse": {
+                "result": [
+                    {
+                        "symbol": "AAPL",
+                        "longName": "Apple Inc.",
+                        "quoteType": "EQUITY",
+                        "currency": "usd",
+                        "regularMarketPrice": 150.5,
+                        "fullExchangeName": "NasdaqGS",
+                        "regularMarketChange": 1.5,
+                        "regularMarketChangePercent": 1.0,
+                    },
+                    {
+                        "symbol": "HALT",
+                        "fullExchangeName": "NYSE",
+                    },
+                ],
+            },
+        });
+
+        let quotes = parse_quotes_batch(&data).unwrap();
+
+        assert_eq!(quotes.len(), 1);
+        let (main_info, extended_info) = &quotes["AAPL"];
+        assert_eq!(main_info.last_quote, Decimal::from_f64(150.5).unwrap());
+        assert_eq!(main_info.currency, "USD");
+        assert_eq!(extended_info.exchange_name, "NasdaqGS");
+    }
+
+    #[test]
+    fn chart_gaps_are_read_from_the_nulls() {
+        let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/data/fixtures/chart/DEMO.json");
+        let mut data: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(fixture).unwrap()).unwrap();
+        let result = &mut data["chart"]["result"][0];
+        result["timestamp"] = serde_json::json!([1678113000, 1678116600, 1678120200]);
+        result["indicators"] = serde_json::json!({
+            "quote": [{
+                "open": [0.0, null, 1.0],
+                "high": [0.0, null, 1.5],
+                "low": [0.0, null, 0.5],
+                "close": [0.0, null, 1.0],
+                "volume": [0, null, null],
+            }],
+            "adjclose": [{
+                "adjclose": [0.0, null, null],
+            }],
+        });
+
+        let chart_info = parse_chart_info(data).unwrap();
+
+        assert_eq!(chart_info.quotes.len(), 2);
+        assert_eq!(
+            chart_info.gaps,
+            [
+                QuoteGaps::default(),
+                QuoteGaps {
+                    volume: true,
+                    adjclose: true,
+                    ..QuoteGaps::default()
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,300 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use glib::subclass::types::ObjectSubclassIsExt;
+use glib::{clone::Downgrade, MainContext, WeakRef, PRIORITY_DEFAULT};
+
+use crate::data_helper::{stox_get_quotes_batch, ExtendedInfo, MainInfo};
+use crate::error::{Result, StoxError};
+use crate::sidebar_item::StoxSidebarItem;
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+type SidebarInfo = Result<(MainInfo, ExtendedInfo)>;
+
+/// Refreshes every subscribed sidebar row from a single background thread.
+///
+/// Rows subscribe while they are visible and unsubscribe when they are hidden,
+/// scrolled away or removed, so only what's on screen is fetched. A newly
+/// subscribed symbol is fetched right away instead of waiting for the next
+/// interval.
+struct RefreshScheduler {
+    subscriptions: RefCell<Subscriptions<WeakRef<StoxSidebarItem>>>,
+}
+
+thread_local! {
+    static SCHEDULER: RefreshScheduler = RefreshScheduler::new();
+}
+
+impl RefreshScheduler {
+    fn new() -> Self {
+        let due = Due::default();
+
+        let (wake, wake_receiver) = mpsc::channel();
+        let (sender, receiver) = MainContext::channel(PRIORITY_DEFAULT);
+
+        {
+            let due = due.clone();
+
+            std::thread::spawn(move || {
+                let mut next_tick = Instant::now() + REFRESH_INTERVAL;
+
+                loop {
+                    let timeout = next_tick.saturating_duration_since(Instant::now());
+                    let batch = match wake_receiver.recv_timeout(timeout) {
+                        Ok(()) => {
+                            while wake_receiver.try_recv().is_ok() {}
+                            due.take_pending()
+                        }
+                        Err(RecvTimeoutError::Timeout) => {
+                            next_tick = Instant::now() + REFRESH_INTERVAL;
+                            due.take_all()
+                        }
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    };
+
+                    if !batch.is_empty() && sender.send(fetch(&batch)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        receiver.attach(None, |results: Vec<(String, SidebarInfo)>| {
+            SCHEDULER.with(|scheduler| scheduler.dispatch(&results));
+
+            glib::Continue(true)
+        });
+
+        Self {
+            subscriptions: RefCell::new(Subscriptions::new(due, wake)),
+        }
+    }
+
+    fn subscribe(&self, symbol: &str, row: &StoxSidebarItem) {
+        self.subscriptions
+            .borrow_mut()
+            .subscribe(symbol, row.downgrade());
+    }
+
+    fn refresh_all(&self) {
+        self.subscriptions.borrow().refresh_all();
+    }
+
+    fn unsubscribe(&self, symbol: &str, row: &StoxSidebarItem) {
+        self.subscriptions.borrow_mut().unsubscribe(symbol, |weak| {
+            weak.upgrade().as_ref().is_some_and(|other| other != row)
+        });
+    }
+
+    fn dispatch(&self, results: &[(String, SidebarInfo)]) {
+        for (symbol, complete_info) in results {
+            // Collect first so a row can unsubscribe while it is being updated
+            let subscribers: Vec<StoxSidebarItem> = self
+                .subscriptions
+                .borrow()
+                .rows(symbol)
+                .iter()
+                .filter_map(WeakRef::upgrade)
+                .collect();
+
+            for row in subscribers {
+                row.imp().show_info(complete_info);
+            }
+        }
+    }
+}
+
+/// The symbols the fetch thread is due to fetch, shared with it.
+#[derive(Clone, Default)]
+struct Due {
+    /// Every subscribed symbol, fetched every interval
+    symbols: Arc<Mutex<Vec<String>>>,
+    /// Fetched as soon as the thread is woken up
+    pending: Arc<Mutex<Vec<String>>>,
+}
+
+impl Due {
+    fn take_pending(&self) -> Vec<String> {
+        std::mem::take(&mut *self.pending.lock().unwrap())
+    }
+
+    /// Every subscribed symbol, which leaves nothing pending.
+    fn take_all(&self) -> Vec<String> {
+        self.pending.lock().unwrap().clear();
+        self.symbols.lock().unwrap().clone()
+    }
+}
+
+/// Which rows show which symbol. Kept apart from the rows and the fetch
+/// thread, a symbol is fetched for as long as any row is subscribed to it.
+struct Subscriptions<R> {
+    rows: HashMap<String, Vec<R>>,
+    due: Due,
+    wake: mpsc::Sender<()>,
+}
+
+impl<R> Subscriptions<R> {
+    fn new(due: Due, wake: mpsc::Sender<()>) -> Self {
+        Self {
+            rows: HashMap::new(),
+            due,
+            wake,
+        }
+    }
+
+    fn subscribe(&mut self, symbol: &str, row: R) {
+        let subscribers = self.rows.entry(symbol.to_owned()).or_default();
+        subscribers.push(row);
+
+        if subscribers.len() == 1 {
+            self.due.symbols.lock().unwrap().push(symbol.to_owned());
+        }
+
+        self.due.pending.lock().unwrap().push(symbol.to_owned());
+        self.wake.send(()).ok();
+    }
+
+    fn refresh_all(&self) {
+        let symbols = self.due.symbols.lock().unwrap().clone();
+        self.due.pending.lock().unwrap().extend(symbols);
+        self.wake.send(()).ok();
+    }
+
+    /// Keeps the rows of `symbol` that `keep` returns true for.
+    fn unsubscribe(&mut self, symbol: &str, keep: impl FnMut(&R) -> bool) {
+        let Some(subscribers) = self.rows.get_mut(symbol) else {
+            return;
+        };
+
+        subscribers.retain(keep);
+
+        if subscribers.is_empty() {
+            self.rows.remove(symbol);
+            self.due
+                .symbols
+                .lock()
+                .unwrap()
+                .retain(|other| other != symbol);
+        }
+    }
+
+    fn rows(&self, symbol: &str) -> &[R] {
+        self.rows.get(symbol).map_or(&[], Vec::as_slice)
+    }
+}
+
+/// Everything due is fetched in one batch. If the batch fails every row gets
+/// the same error.
+fn fetch(symbols: &[String]) -> Vec<(String, SidebarInfo)> {
+    let symbols: Vec<&str> = symbols.iter().map(String::as_str).collect();
+
+    match stox_get_quotes_batch(&symbols) {
+        Ok(mut quotes) => symbols
+            .iter()
+            .map(|symbol| {
+                let info = quotes.remove(*symbol).ok_or(StoxError::UnknownSymbol);
+                (symbol.to_string(), info)
+            })
+            .collect(),
+        Err(err) => symbols
+            .iter()
+            .map(|symbol| (symbol.to_string(), Err(err.clone())))
+            .collect(),
+    }
+}
+
+pub fn subscribe(symbol: &str, row: &StoxSidebarItem) {
+    SCHEDULER.with(|scheduler| scheduler.subscribe(symbol, row));
+}
+
+pub fn unsubscribe(symbol: &str, row: &StoxSidebarItem) {
+    SCHEDULER.with(|scheduler| scheduler.unsubscribe(symbol, row));
+}
+
+/// Fetches every subscribed symbol now instead of at the next interval.
+pub fn refresh_all() {
+    SCHEDULER.with(|scheduler| scheduler.refresh_all());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subscriptions() -> (Subscriptions<u32>, Due, mpsc::Receiver<()>) {
+        let due = Due::default();
+        let (wake, wake_receiver) = mpsc::channel();
+
+        (Subscriptions::new(due.clone(), wake), due, wake_receiver)
+    }
+
+    #[test]
+    fn new_subscriptions_are_fetched_right_away() {
+        let (mut subscriptions, due, wake_receiver) = subscriptions();
+
+        subscriptions.subscribe("AAPL", 1);
+
+        assert!(wake_receiver.try_recv().is_ok());
+        assert_eq!(due.take_pending(), ["AAPL"]);
+        assert!(due.take_pending().is_empty());
+        assert_eq!(due.take_all(), ["AAPL"]);
+    }
+
+    #[test]
+    fn symbols_are_fetched_once_however_many_rows_show_them() {
+        let (mut subscriptions, due, _wake_receiver) = subscriptions();
+
+        subscriptions.subscribe("AAPL", 1);
+        subscriptions.subscribe("AAPL", 2);
+        subscriptions.subscribe("MSFT", 3);
+
+        assert_eq!(subscriptions.rows("AAPL"), [1, 2]);
+        assert_eq!(due.take_all(), ["AAPL", "MSFT"]);
+    }
+
+    #[test]
+    fn symbols_are_fetched_until_the_last_row_unsubscribes() {
+        let (mut subscriptions, due, _wake_receiver) = subscriptions();
+        subscriptions.subscribe("AAPL", 1);
+        subscriptions.subscribe("AAPL", 2);
+
+        subscriptions.unsubscribe("AAPL", |row| *row != 1);
+
+        assert_eq!(subscriptions.rows("AAPL"), [2]);
+        assert_eq!(due.take_all(), ["AAPL"]);
+
+        subscriptions.unsubscribe("AAPL", |row| *row != 2);
+
+        assert!(subscriptions.rows("AAPL").is_empty());
+        assert!(subscriptions.rows.is_empty());
+        assert!(due.take_all().is_empty());
+    }
+
+    #[test]
+    fn unsubscribing_an_unknown_symbol_does_nothing() {
+        let (mut subscriptions, due, _wake_receiver) = subscriptions();
+        subscriptions.subscribe("AAPL", 1);
+
+        subscriptions.unsubscribe("MSFT", |_| false);
+
+        assert_eq!(subscriptions.rows("AAPL"), [1]);
+        assert_eq!(due.take_all(), ["AAPL"]);
+    }
+
+    #[test]
+    fn refresh_all_fetches_every_symbol_right_away() {
+        let (mut subscriptions, due, wake_receiver) = subscriptions();
+        subscriptions.subscribe("AAPL", 1);
+        subscriptions.subscribe("MSFT", 2);
+        due.take_pending();
+        while wake_receiver.try_recv().is_ok() {}
+
+        subscriptions.refresh_all();
+
+        assert!(wake_receiver.try_recv().is_ok());
+        assert_eq!(due.take_pending(), ["AAPL", "MSFT"]);
+    }
+}
@@ -1,4 +1,4 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 
 use gettextrs::gettext;
 
@@ -10,7 +10,10 @@ use gtk4::*;
 
 use once_cell::sync::Lazy;
 
-use crate::data_helper::stox_get_sidebar_info;
+use crate::data_helper::{ExtendedInfo, MainInfo};
+use crate::error::Result;
+use crate::format_helper::{fmt_as_of, fmt_change, fmt_price};
+use crate::refresh_scheduler;
 
 #[derive(Default, CompositeTemplate)]
 #[template(resource = "/org/itzswirlz/stox/resources/ui/stoxsidebaritem.ui")]
@@ -23,8 +26,12 @@ pub struct StoxSidebarItem {
     quote_label: TemplateChild<Label>,
     #[template_child]
     market_change_label: TemplateChild<Label>,
+    #[template_child]
+    as_of_label: TemplateChild<Label>,
     symbol: RefCell<String>,
     searched: RefCell<bool>,
+    off_screen: Cell<bool>,
+    subscribed: RefCell<Option<String>>,
 }
 
 #[glib::object_subclass]
@@ -93,7 +100,7 @@ impl ObjectImpl for StoxSidebarItem {
             .get()
             .set_tooltip_text(Some(&self.symbol.borrow()));
 
-        self.tick();
+        self.update_subscription();
     }
 }
 
@@ -101,74 +108,108 @@ impl BuildableImpl for StoxSidebarItem {}
 
 impl ListBoxRowImpl for StoxSidebarItem {}
 
-impl WidgetImpl for StoxSidebarItem {}
+impl WidgetImpl for StoxSidebarItem {
+    fn map(&self) {
+        self.parent_map();
+        self.update_subscription();
+    }
+
+    fn unmap(&self) {
+        self.parent_unmap();
+        self.update_subscription();
+    }
+}
 
 impl StoxSidebarItem {
-    pub fn tick(&self) {
+    pub fn set_off_screen(&self, off_screen: bool) {
+        self.off_screen.set(off_screen);
+        self.update_subscription();
+    }
+
+    /// Only rows that can actually be seen are kept up to date.
+    fn update_subscription(&self) {
+        let obj = self.obj();
         let symbol = self.symbol.borrow().to_string();
+
+        let wanted =
+            (obj.is_mapped() && !self.off_screen.get() && !symbol.is_empty()).then_some(symbol);
+        if *self.subscribed.borrow() == wanted {
+            return;
+        }
+
+        if let Some(symbol) = self.subscribed.take() {
+            refresh_scheduler::unsubscribe(&symbol, &obj);
+        }
+
+        if let Some(symbol) = &wanted {
+            refresh_scheduler::subscribe(symbol, &obj);
+        }
+
+        *self.subscribed.borrow_mut() = wanted;
+    }
+
+    pub fn show_info(&self, complete_info: &Result<(MainInfo, ExtendedInfo)>) {
         let desc_label = self.desc_label.get();
         let quote_label = self.quote_label.get();
         let symbol_label = self.symbol_label.get();
         let market_change_label = self.market_change_label.get();
+        let as_of_label = self.as_of_label.get();
 
         let settings = gio::Settings::new(crate::APP_ID);
-        if symbol.is_empty() {
-            return;
-        }
-
-        let (sender, receiver) = MainContext::channel(PRIORITY_DEFAULT);
 
-        std::thread::spawn(move || loop {
-            match stox_get_sidebar_info(&symbol) {
-                Ok(main_info) => sender.send(Some(main_info)).unwrap(),
-                Err(_) => sender.send(None).unwrap(),
-            }
-            std::thread::sleep(std::time::Duration::from_secs(5));
-        });
+        match complete_info {
+            Ok((main_info, extended_info)) => {
+                let last_quote = fmt_price(main_info.last_quote, &main_info.currency);
+                quote_label.set_text(&last_quote);
+                quote_label.set_tooltip_text(Some(&last_quote));
 
-        receiver.attach(None, move |complete_info| {
-            match complete_info {
-                Some((main_info, extended_info)) => {
-                    quote_label.set_text(&main_info.last_quote);
-                    quote_label.set_tooltip_text(Some(&main_info.last_quote));
+                desc_label.set_text(&main_info.name);
+                desc_label.set_tooltip_text(Some(&main_info.name));
 
-                    desc_label.set_text(&main_info.name);
-                    desc_label.set_tooltip_text(Some(&main_info.name));
+                let market_change = fmt_change(extended_info.market_change);
+                market_change_label.set_text(&market_change);
+                market_change_label.set_tooltip_text(Some(&market_change));
 
-                    market_change_label.set_text(&extended_info.market_change);
-                    market_change_label.set_tooltip_text(Some(&extended_info.market_change));
-
-                    if extended_info.market_change_neg() {
-                        market_change_label.set_css_classes(&["market_change_neg"]);
-                    } else {
-                        market_change_label.set_css_classes(&["market_change_pos"]);
-                    }
+                if extended_info.market_change_neg() {
+                    market_change_label.set_css_classes(&["market_change_neg"]);
+                } else {
+                    market_change_label.set_css_classes(&["market_change_pos"]);
+                }
 
-                    if main_info.bankruptcy && settings.boolean("sidebar-color-bankruptcy") {
-                        symbol_label.add_css_class("symbol_bankruptcy");
-                        symbol_label.set_tooltip_text(Some(&gettext(
-                            "This company may be undergoing bankruptcy.",
-                        )));
+                match main_info.as_of.or(extended_info.as_of) {
+                    Some(as_of) => {
+                        as_of_label.set_text(&fmt_as_of(as_of));
+                        as_of_label.show();
                     }
+                    None => as_of_label.hide(),
+                }
 
-                    if main_info.instrument_type == "FUTURE"
-                        && settings.boolean("sidebar-color-futures")
-                    {
-                        symbol_label.add_css_class("symbol_future");
-                    } else if main_info.instrument_type == "ETF"
-                        && settings.boolean("sidebar-color-etfs")
-                    {
-                        symbol_label.add_css_class("symbol_etf");
-                    }
+                if main_info.bankruptcy && settings.boolean("sidebar-color-bankruptcy") {
+                    symbol_label.add_css_class("symbol_bankruptcy");
+                    symbol_label.set_tooltip_text(Some(&gettext(
+                        "This company may be undergoing bankruptcy.",
+                    )));
                 }
-                None => {
-                    quote_label.set_text("???");
-                    desc_label.set_text("???");
-                    market_change_label.set_text("???");
+
+                if main_info.instrument_type == "FUTURE"
+                    && settings.boolean("sidebar-color-futures")
+                {
+                    symbol_label.add_css_class("symbol_future");
+                } else if main_info.instrument_type == "ETF"
+                    && settings.boolean("sidebar-color-etfs")
+                {
+                    symbol_label.add_css_class("symbol_etf");
                 }
             }
-
-            Continue(true)
-        });
+            Err(err) => {
+                quote_label.set_text("???");
+                market_change_label.set_text("???");
+                as_of_label.hide();
+
+                let message = err.message();
+                desc_label.set_text(&message);
+                desc_label.set_tooltip_text(Some(&message));
+            }
+        }
     }
 }
@@ -1,5 +1,7 @@
 mod imp;
 
+use glib::subclass::types::ObjectSubclassIsExt;
+
 use gtk4::*;
 
 glib::wrapper! {
@@ -16,8 +18,8 @@ impl StoxSidebarItem {
             .build()
     }
 
-    pub fn tick(item: imp::StoxSidebarItem) {
-        // fancy hack for making the function use the implementation
-        imp::StoxSidebarItem::tick(&item);
+    /// Rows scrolled out of the sidebar stop refreshing until they come back.
+    pub fn set_off_screen(&self, off_screen: bool) {
+        self.imp().set_off_screen(off_screen);
     }
 }